@@ -1,14 +1,16 @@
+use std::cell::Cell;
+
 use crate::lexer::TokenPos as Pos;
 use crate::intern::Symbol;
 
-#[derive(Debug, PartialEq)]
-pub enum Var<'a> {
+#[derive(Debug)]
+pub enum Var {
     Simple(Symbol, Pos),
-    Field(Box<Var<'a>>, Symbol, Pos),
-    Subscript(Box<Var<'a>>, Box<Expr<'a>>, Pos),
+    Field(Box<Var>, Symbol, Pos),
+    Subscript(Box<Var>, Box<Expr>, Pos),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Op {
     Plus,
     Minus,
@@ -22,105 +24,112 @@ pub enum Op {
     Ge,
 }
 
-#[derive(Debug, PartialEq)]
-pub enum Expr<'a> {
-    VarRef(Box<Var<'a>>),
+#[derive(Debug)]
+pub enum Expr {
+    VarRef(Box<Var>),
     Nil,
     Int(i32),
-    String(&'a str),
+    // Interned at parse time, like every other name -- downstream passes no
+    // longer need to carry the source's `'source` borrow around.
+    String(Symbol),
     Call {
         func: Symbol,
-        args: Vec<Expr<'a>>,
+        args: Vec<Expr>,
         pos: Pos,
     },
     BinOp {
-        left: Box<Expr<'a>>,
+        left: Box<Expr>,
         oper: Op,
-        right: Box<Expr<'a>>,
+        right: Box<Expr>,
         pos: Pos,
     },
     Record {
-        fields: Vec<(Symbol, Box<Expr<'a>>, Pos)>,
+        fields: Vec<(Symbol, Box<Expr>, Pos)>,
         rtype: Symbol,
         pos: Pos,
     },
-    Seq(Vec<(Box<Expr<'a>>, Pos)>),
+    Seq(Vec<(Box<Expr>, Pos)>),
     Assign {
-        var: Var<'a>,
-        expr: Box<Expr<'a>>,
+        var: Var,
+        expr: Box<Expr>,
         pos: Pos,
     },
     If {
-        test: Box<Expr<'a>>,
-        then_branch: Box<Expr<'a>>,
-        else_branch: Option<Box<Expr<'a>>>,
+        test: Box<Expr>,
+        then_branch: Box<Expr>,
+        else_branch: Option<Box<Expr>>,
         pos: Pos,
     },
     While {
-        test: Box<Expr<'a>>,
-        body: Box<Expr<'a>>,
+        test: Box<Expr>,
+        body: Box<Expr>,
         pos: Pos,
     },
     For {
         var: Symbol,
-        // todo: escape
-        lo: Box<Expr<'a>>,
-        hi: Box<Expr<'a>>,
+        // Set by the `escape` pass: does an inner function close over `var`?
+        escape: Cell<bool>,
+        lo: Box<Expr>,
+        hi: Box<Expr>,
+        body: Box<Expr>,
         pos: Pos,
     },
     Break(Pos),
     Let {
-        decls: Vec<Box<Decl<'a>>>,
-        body: Box<Expr<'a>>,
+        decls: Vec<Box<Decl>>,
+        body: Box<Expr>,
         pos: Pos,
     },
     Array {
         etype: Symbol,
-        size: Box<Expr<'a>>,
-        init: Box<Expr<'a>>,
+        size: Box<Expr>,
+        init: Box<Expr>,
         pos: Pos,
     },
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 pub struct Field {
-    name: Symbol,
-    // todo: escape,
-    ftype: Symbol,
-    pos: Pos,
+    pub name: Symbol,
+    // Set by the `escape` pass: does an inner function close over this
+    // parameter/field binding?
+    pub escape: Cell<bool>,
+    pub ftype: Symbol,
+    pub pos: Pos,
 }
 
-#[derive(Debug, PartialEq)]
-pub struct Fundecl<'a> {
-    name: Symbol,
-    params: Vec<Box<Field>>,
-    result: Option<(Symbol, Pos)>,
-    body: Box<Expr<'a>>,
-    pos: Pos,
+#[derive(Debug)]
+pub struct Fundecl {
+    pub name: Symbol,
+    pub params: Vec<Box<Field>>,
+    pub result: Option<(Symbol, Pos)>,
+    pub body: Box<Expr>,
+    pub pos: Pos,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 pub struct Typedecl {
-    name: Symbol,
-    ty: Ty,
-    pos: Pos,
+    pub name: Symbol,
+    pub ty: Ty,
+    pub pos: Pos,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 pub enum Ty {
     Name(Symbol, Pos),
     Record(Vec<Box<Field>>),
     Array(Symbol, Pos),
 }
 
-#[derive(Debug, PartialEq)]
-pub enum Decl<'a> {
-    Function(Vec<Box<Fundecl<'a>>>),
+#[derive(Debug)]
+pub enum Decl {
+    Function(Vec<Box<Fundecl>>),
     Var {
         name: Symbol,
-        // todo: escape
+        // Set by the `escape` pass: does an inner function close over `name`?
+        escape: Cell<bool>,
         vtype: Option<(Symbol, Pos)>,
-        init: Box<Expr<'a>>,
+        init: Box<Expr>,
         pos: Pos,
     },
     Type(Vec<Box<Typedecl>>),
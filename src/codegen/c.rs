@@ -0,0 +1,391 @@
+//! A portable C backend: prints a single `.c` translation unit where every
+//! [`Value`] becomes a local variable of an opaque `tiger_val` type, backed
+//! by a small runtime (`tiger_runtime.h`) that isn't part of this crate.
+
+use std::collections::HashSet;
+
+use super::{Backend, Label, Value};
+use crate::ast::Op;
+
+fn op_str(op: &Op) -> &'static str {
+    match op {
+        Op::Plus => "+",
+        Op::Minus => "-",
+        Op::Times => "*",
+        Op::Divide => "/",
+        Op::Eq => "==",
+        Op::Neq => "!=",
+        Op::Lt => "<",
+        Op::Le => "<=",
+        Op::Gt => ">",
+        Op::Ge => ">=",
+    }
+}
+
+/// The C source for one function that's still being generated: its own
+/// text, indentation, and the `Value`s already declared inside it. Kept
+/// separate per function so that a `Fundecl` generated partway through
+/// another function's body -- the only way that happens here is the
+/// synthetic `main` wrapper `Generator::gen` builds around the whole
+/// program, since genuinely nested `function`s are rejected before codegen
+/// sees them -- doesn't inherit the enclosing function's declarations.
+struct OpenFn {
+    text: String,
+    indent: usize,
+    declared: HashSet<Value>,
+}
+
+impl OpenFn {
+    fn new(signature: String) -> Self {
+        OpenFn {
+            text: signature,
+            indent: 1,
+            declared: HashSet::new(),
+        }
+    }
+
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.indent {
+            self.text.push_str("    ");
+        }
+        self.text.push_str(text);
+        self.text.push('\n');
+    }
+}
+
+pub struct CBackend {
+    // Completed function bodies, in the order each finished generating.
+    // A function finishes before the rest of its `let` uses it (decls run
+    // before a `let`'s body), so those uses follow its definition with no
+    // further sorting. Within one group of `function` declarations, a call
+    // to a sibling declared later in the group comes before that sibling's
+    // definition, and no prototypes are emitted for it. Since a function
+    // declared while another is still open is appended here as soon as it closes, rather
+    // than inlined into the enclosing function's text, each one lands as
+    // its own top-level C function instead of nesting inside another's
+    // braces, which C does not allow.
+    functions: Vec<String>,
+    open: Vec<OpenFn>,
+    next_label: usize,
+    next_temp: u32,
+}
+
+impl Default for CBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CBackend {
+    pub fn new() -> Self {
+        CBackend {
+            functions: Vec::new(),
+            open: Vec::new(),
+            next_label: 0,
+            next_temp: 0,
+        }
+    }
+
+    fn name(v: Value) -> String {
+        match v {
+            Value::Reg(r) => format!("r{}", r),
+            Value::Stack(s) => format!("s{}", -s),
+            Value::Imm(i) => i.to_string(),
+        }
+    }
+
+    fn current(&mut self) -> &mut OpenFn {
+        self.open
+            .last_mut()
+            .expect("codegen: C backend emitted outside of any open function")
+    }
+
+    fn line(&mut self, text: &str) {
+        self.current().line(text);
+    }
+
+    /// A register/stack `Value` needs its local declared before first use;
+    /// an immediate is just inlined as a literal. Only the first write to a
+    /// given `Value` in the current function emits the declaration --
+    /// subsequent writes (loop counters, reassignment) are plain stores.
+    fn declare(&mut self, v: Value) {
+        if !matches!(v, Value::Imm(_)) && self.current().declared.insert(v) {
+            self.line(&format!("tiger_val {};", Self::name(v)));
+        }
+    }
+}
+
+impl Backend for CBackend {
+    type Output = String;
+
+    // Transient temporaries live in a register range (100..=255) that
+    // `RegisterFile` never hands out to named bindings, so the two
+    // allocators can't collide. Running past the end of that range panics
+    // rather than wrapping around onto the named registers.
+    fn temp(&mut self) -> Value {
+        let t = u8::try_from(100 + self.next_temp)
+            .expect("codegen: C backend ran out of temporary registers");
+        self.next_temp += 1;
+        Value::Reg(t)
+    }
+
+    fn emit_int(&mut self, dst: Value, n: i32) {
+        self.declare(dst);
+        self.line(&format!("{} = {};", Self::name(dst), n));
+    }
+
+    fn emit_string(&mut self, dst: Value, s: &str) {
+        self.declare(dst);
+        self.line(&format!(
+            "{} = tiger_string(\"{}\");",
+            Self::name(dst),
+            s.escape_default()
+        ));
+    }
+
+    fn emit_nil(&mut self, dst: Value) {
+        self.declare(dst);
+        self.line(&format!("{} = TIGER_NIL;", Self::name(dst)));
+    }
+
+    fn emit_move(&mut self, dst: Value, src: Value) {
+        self.declare(dst);
+        self.line(&format!("{} = {};", Self::name(dst), Self::name(src)));
+    }
+
+    fn emit_binop(&mut self, dst: Value, op: &Op, left: Value, right: Value) {
+        self.declare(dst);
+        self.line(&format!(
+            "{} = {} {} {};",
+            Self::name(dst),
+            Self::name(left),
+            op_str(op),
+            Self::name(right)
+        ));
+    }
+
+    fn emit_call(&mut self, dst: Value, func: &str, args: &[Value]) {
+        self.declare(dst);
+        let arg_list: Vec<String> = args.iter().map(|v| Self::name(*v)).collect();
+        self.line(&format!(
+            "{} = tiger_{}({});",
+            Self::name(dst),
+            func,
+            arg_list.join(", ")
+        ));
+    }
+
+    fn emit_record(&mut self, dst: Value, rtype: &str, fields: &[(&str, Value)]) {
+        self.declare(dst);
+        self.line(&format!(
+            "{} = tiger_alloc_record(/* {} */ {});",
+            Self::name(dst),
+            rtype,
+            fields.len()
+        ));
+        for (i, (field, v)) in fields.iter().enumerate() {
+            self.line(&format!(
+                "{}.fields[{}] = {}; /* {} */",
+                Self::name(dst),
+                i,
+                Self::name(*v),
+                field
+            ));
+        }
+    }
+
+    fn emit_array(&mut self, dst: Value, etype: &str, size: Value, init: Value) {
+        self.declare(dst);
+        self.line(&format!(
+            "{} = tiger_alloc_array(/* {} */ {}, {});",
+            Self::name(dst),
+            etype,
+            Self::name(size),
+            Self::name(init)
+        ));
+    }
+
+    fn emit_subscript(&mut self, dst: Value, array: Value, index: Value) {
+        self.declare(dst);
+        self.line(&format!(
+            "{} = {}.elems[{}];",
+            Self::name(dst),
+            Self::name(array),
+            Self::name(index)
+        ));
+    }
+
+    fn emit_field(&mut self, dst: Value, record: Value, field: &str) {
+        self.declare(dst);
+        self.line(&format!(
+            "{} = {}.{};",
+            Self::name(dst),
+            Self::name(record),
+            field
+        ));
+    }
+
+    fn emit_store_subscript(&mut self, array: Value, index: Value, value: Value) {
+        self.line(&format!(
+            "{}.elems[{}] = {};",
+            Self::name(array),
+            Self::name(index),
+            Self::name(value)
+        ));
+    }
+
+    fn emit_store_field(&mut self, record: Value, field: &str, value: Value) {
+        self.line(&format!(
+            "{}.{} = {};",
+            Self::name(record),
+            field,
+            Self::name(value)
+        ));
+    }
+
+    fn new_label(&mut self) -> Label {
+        let l = Label::new(self.next_label);
+        self.next_label += 1;
+        l
+    }
+
+    fn bind_label(&mut self, label: Label) {
+        self.current().indent = self.current().indent.saturating_sub(1);
+        self.line(&format!("L{}:;", label.index()));
+        self.current().indent += 1;
+    }
+
+    fn jump(&mut self, label: Label) {
+        self.line(&format!("goto L{};", label.index()));
+    }
+
+    fn jump_if_false(&mut self, cond: Value, to: Label) {
+        self.line(&format!("if (!{}) goto L{};", Self::name(cond), to.index()));
+    }
+
+    fn enter_function(&mut self, name: &str, params: &[&str]) {
+        let plist: Vec<String> = params.iter().map(|p| format!("tiger_val {}", p)).collect();
+        let signature = format!("tiger_val tiger_{}({}) {{\n", name, plist.join(", "));
+        self.open.push(OpenFn::new(signature));
+    }
+
+    fn emit_return(&mut self, v: Value) {
+        self.line(&format!("return {};", Self::name(v)));
+    }
+
+    fn leave_function(&mut self) {
+        let mut f = self
+            .open
+            .pop()
+            .expect("codegen: leave_function without a matching enter_function");
+        f.indent = f.indent.saturating_sub(1);
+        f.line("}");
+        self.functions.push(f.text);
+    }
+
+    fn finish(self) -> String {
+        assert!(
+            self.open.is_empty(),
+            "codegen: C backend finished with an unclosed function"
+        );
+        let mut out = String::from("#include \"tiger_runtime.h\"\n\n");
+        out.push_str(&self.functions.join("\n"));
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen::Generator;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn gen_c(source: &str) -> String {
+        let mut parser = Parser::new(Lexer::new(source).collect());
+        let expr = parser.parse().expect("source should parse");
+        Generator::new(CBackend::new()).gen(parser.interner_mut(), &expr)
+    }
+
+    #[test]
+    fn arithmetic_lowers_to_flat_c_statements() {
+        let out = gen_c("1 + 2 * 3");
+        assert!(out.contains("r100 = 1;"));
+        assert!(out.contains("r101 = 2;"));
+        assert!(out.contains("r102 = 3;"));
+        assert!(out.contains("r103 = r101 * r102;"));
+        assert!(out.contains("r104 = r100 + r103;"));
+    }
+
+    #[test]
+    fn if_then_else_emits_labels_and_a_join() {
+        let out = gen_c("if 1 then 2 else 3");
+        assert!(out.contains("goto L1;"));
+        assert!(out.contains("L0:;"));
+        assert!(out.contains("L1:;"));
+    }
+
+    #[test]
+    fn let_binding_spans_a_named_register() {
+        let out = gen_c("let var x := 10 in x + 1 end");
+        // `x` gets a real register (r0), distinct from the 100+ range used
+        // for transient temporaries like the literal `10`/`1` and the sum.
+        assert!(out.contains("r100 = 10;"));
+        assert!(out.contains("r0 = r100;"));
+        assert!(out.contains("= r0 + r101;"));
+    }
+
+    #[test]
+    fn for_loop_declares_its_counter_register_only_once() {
+        let out = gen_c("for i := 1 to 10 do ()");
+        // The counter is written twice (the initial move, then the
+        // increment at the bottom of the loop body) but must only be
+        // declared the first time, or the emitted C redeclares `r0`.
+        assert_eq!(out.matches("tiger_val r0;").count(), 1);
+    }
+
+    #[test]
+    fn reassignment_declares_the_target_register_only_once() {
+        let out = gen_c("let var x := 10 in (x := 20; x) end");
+        assert_eq!(out.matches("tiger_val r0;").count(), 1);
+        assert!(out.contains("r0 = r101;"));
+    }
+
+    #[test]
+    fn whole_program_is_wrapped_in_an_entry_function_that_returns() {
+        let out = gen_c("1 + 2 * 3");
+        assert!(out.contains("tiger_val tiger_main() {"));
+        assert!(out.contains("return r104;"));
+        // Balanced braces: every `{` from `enter_function` is matched by a
+        // `}` from `leave_function` -- this is exactly what `cc` rejected
+        // before the root expression was wrapped in a function at all.
+        assert_eq!(
+            out.matches('{').count(),
+            out.matches('}').count(),
+            "unbalanced braces in generated C"
+        );
+    }
+
+    #[test]
+    fn function_declarations_are_flattened_ahead_of_main() {
+        let out = gen_c("let function f(x: int): int = x + 1 in f(1) end");
+        let f_def = out.find("tiger_val tiger_f(").expect("f should be defined");
+        let f_ret = out[f_def..].find("return r").unwrap() + f_def;
+        let main_def = out.find("tiger_val tiger_main()").expect("main should be defined");
+        // `f`'s whole definition, including its own `return`, appears
+        // before `main`'s -- not nested inside `main`'s braces.
+        assert!(f_def < main_def);
+        assert!(f_ret < main_def);
+        assert!(out.contains("return r101;"));
+    }
+
+    #[test]
+    #[should_panic(expected = "nested function declarations are not supported")]
+    fn nested_function_declarations_are_rejected() {
+        gen_c(
+            "let function outer(): int = \
+               let function inner(): int = 1 in inner() end \
+             in outer() end",
+        );
+    }
+}
@@ -0,0 +1,496 @@
+//! Lowering from the `ast` tree to a selectable target. A [`Backend`] only
+//! decides how each AST construct turns into its own output; [`Generator`]
+//! owns the tree walk, variable scoping, and label bookkeeping so a new
+//! backend never has to re-implement them.
+
+pub mod c;
+pub mod vm;
+
+use std::collections::HashMap;
+
+use crate::ast::{Decl, Expr, Fundecl, Op, Var};
+use crate::intern::{Interner, Symbol};
+
+/// How many named bindings (`let` variables, function parameters, `for`
+/// loop counters) can live in registers before [`RegisterFile`] starts
+/// spilling to the stack. Transient values produced while evaluating an
+/// expression are not bindings and don't compete for these -- see
+/// `Backend::temp`.
+pub const NUM_REGS: usize = 6;
+
+/// Where a value lives: a register, a stack slot (negative offsets growing
+/// down from the frame pointer), or a compile-time-known immediate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Value {
+    Reg(u8),
+    Stack(i32),
+    Imm(i64),
+}
+
+/// A forward-referenceable jump target. A backend hands one out from
+/// [`Backend::new_label`] and fixes up the real address/offset once
+/// [`Backend::bind_label`] is called at the destination -- this is the
+/// relocation table for `if`/`while`/`for`/`break`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Label(usize);
+
+impl Label {
+    fn new(idx: usize) -> Self {
+        Label(idx)
+    }
+
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+/// Tracks which declared variable, if any, currently occupies each
+/// register, spilling to a fresh stack slot once the register file fills
+/// up.
+pub struct RegisterFile {
+    regs: [Option<Symbol>; NUM_REGS],
+    next_slot: i32,
+}
+
+impl Default for RegisterFile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RegisterFile {
+    pub fn new() -> Self {
+        RegisterFile {
+            regs: [None; NUM_REGS],
+            next_slot: 0,
+        }
+    }
+
+    /// Claim a register for `owner`, or spill to a fresh stack slot if the
+    /// register file is full.
+    pub fn allocate(&mut self, owner: Symbol) -> Value {
+        match self.regs.iter().position(Option::is_none) {
+            Some(r) => {
+                self.regs[r] = Some(owner);
+                Value::Reg(r as u8)
+            }
+            None => {
+                self.next_slot -= 1;
+                Value::Stack(self.next_slot)
+            }
+        }
+    }
+
+    /// Give a register back to the pool. No-op for stack slots: once a
+    /// binding spills, its slot is never reused.
+    pub fn free(&mut self, value: Value) {
+        if let Value::Reg(r) = value {
+            self.regs[r as usize] = None;
+        }
+    }
+}
+
+/// One code-generation target. `Generator` drives the AST walk and calls
+/// these methods for each construct; field/record names are passed as
+/// plain strings, since codegen does not consult the `semant` pass's types
+/// to resolve them to indices.
+pub trait Backend {
+    type Output;
+
+    /// Allocate storage for a value that isn't a named binding (an
+    /// intermediate result of evaluating an expression).
+    fn temp(&mut self) -> Value;
+
+    fn emit_int(&mut self, dst: Value, n: i32);
+    fn emit_string(&mut self, dst: Value, s: &str);
+    fn emit_nil(&mut self, dst: Value);
+    fn emit_move(&mut self, dst: Value, src: Value);
+    fn emit_binop(&mut self, dst: Value, op: &Op, left: Value, right: Value);
+    fn emit_call(&mut self, dst: Value, func: &str, args: &[Value]);
+    fn emit_record(&mut self, dst: Value, rtype: &str, fields: &[(&str, Value)]);
+    fn emit_array(&mut self, dst: Value, etype: &str, size: Value, init: Value);
+    fn emit_subscript(&mut self, dst: Value, array: Value, index: Value);
+    fn emit_field(&mut self, dst: Value, record: Value, field: &str);
+    fn emit_store_subscript(&mut self, array: Value, index: Value, value: Value);
+    fn emit_store_field(&mut self, record: Value, field: &str, value: Value);
+
+    fn new_label(&mut self) -> Label;
+    fn bind_label(&mut self, label: Label);
+    fn jump(&mut self, label: Label);
+    fn jump_if_false(&mut self, cond: Value, to: Label);
+
+    fn enter_function(&mut self, name: &str, params: &[&str]);
+    fn leave_function(&mut self);
+
+    /// Return `v` from the function body currently open. Called once, right
+    /// before the matching [`Backend::leave_function`], with the value the
+    /// body's last expression produced.
+    fn emit_return(&mut self, v: Value);
+
+    /// Consume the generator and hand back whatever the backend produced.
+    fn finish(self) -> Self::Output;
+}
+
+/// Drives a `Backend` over an `ast::Expr` tree. A whole Tiger program is a
+/// single top-level `Expr` (typically a `let ... in ... end`), so there is
+/// one entry point: [`Generator::gen`].
+pub struct Generator<B: Backend> {
+    backend: B,
+    regs: RegisterFile,
+    scopes: Vec<HashMap<Symbol, Value>>,
+    loop_exits: Vec<Label>,
+    // How many *user* `Fundecl` bodies are currently open. The synthetic
+    // `main` wrapper `gen` emits around the whole program doesn't count --
+    // only real nesting of one Tiger `function` inside another's body does.
+    function_depth: usize,
+}
+
+impl<B: Backend> Generator<B> {
+    pub fn new(backend: B) -> Self {
+        Generator {
+            backend,
+            regs: RegisterFile::new(),
+            scopes: vec![HashMap::new()],
+            loop_exits: Vec::new(),
+            function_depth: 0,
+        }
+    }
+
+    /// A Tiger program is a single top-level `Expr`, but every `Backend`
+    /// target (a C translation unit, the VM's instruction stream) needs an
+    /// enclosing function to hold it and a `return` of its final value --
+    /// so `gen` wraps the whole program in a synthetic `main` entry point.
+    pub fn gen(mut self, interner: &mut Interner, expr: &Expr) -> B::Output {
+        self.backend.enter_function("main", &[]);
+        let result = self.gen_expr(interner, expr);
+        self.backend.emit_return(result);
+        self.backend.leave_function();
+        self.backend.finish()
+    }
+
+    fn bind(&mut self, sym: Symbol, value: Value) {
+        self.scopes.last_mut().unwrap().insert(sym, value);
+    }
+
+    /// Pop the innermost scope and return every register it bound to the
+    /// free pool, so a sibling or outer binding can reuse it once this
+    /// scope's names go out of scope. Stack slots are left alone --
+    /// `RegisterFile::free` is already a no-op for them.
+    fn pop_scope(&mut self) {
+        let scope = self.scopes.pop().expect("scope stack underflow");
+        for value in scope.into_values() {
+            self.regs.free(value);
+        }
+    }
+
+    fn lookup(&self, sym: Symbol) -> Value {
+        for scope in self.scopes.iter().rev() {
+            if let Some(v) = scope.get(&sym) {
+                return *v;
+            }
+        }
+        panic!("codegen: reference to unbound variable (semantic analysis should catch this)");
+    }
+
+    fn gen_var_load(&mut self, interner: &mut Interner, var: &Var) -> Value {
+        match var {
+            Var::Simple(sym, _) => self.lookup(*sym),
+            Var::Field(base, field, _) => {
+                let base_val = self.gen_var_load(interner, base);
+                let name = interner.name(*field);
+                let dst = self.backend.temp();
+                self.backend.emit_field(dst, base_val, name);
+                dst
+            }
+            Var::Subscript(base, index, _) => {
+                let base_val = self.gen_var_load(interner, base);
+                let index_val = self.gen_expr(interner, index);
+                let dst = self.backend.temp();
+                self.backend.emit_subscript(dst, base_val, index_val);
+                dst
+            }
+        }
+    }
+
+    fn gen_assign(&mut self, interner: &mut Interner, var: &Var, value: Value) {
+        match var {
+            Var::Simple(sym, _) => {
+                let slot = self.lookup(*sym);
+                self.backend.emit_move(slot, value);
+            }
+            Var::Field(base, field, _) => {
+                let base_val = self.gen_var_load(interner, base);
+                let name = interner.name(*field);
+                self.backend.emit_store_field(base_val, name, value);
+            }
+            Var::Subscript(base, index, _) => {
+                let base_val = self.gen_var_load(interner, base);
+                let index_val = self.gen_expr(interner, index);
+                self.backend.emit_store_subscript(base_val, index_val, value);
+            }
+        }
+    }
+
+    fn gen_expr(&mut self, interner: &mut Interner, expr: &Expr) -> Value {
+        match expr {
+            Expr::Nil => {
+                let dst = self.backend.temp();
+                self.backend.emit_nil(dst);
+                dst
+            }
+            Expr::Int(n) => {
+                let dst = self.backend.temp();
+                self.backend.emit_int(dst, *n);
+                dst
+            }
+            Expr::String(s) => {
+                let dst = self.backend.temp();
+                self.backend.emit_string(dst, interner.name(*s));
+                dst
+            }
+            Expr::VarRef(var) => self.gen_var_load(interner, var),
+            Expr::Call { func, args, .. } => {
+                let mut arg_vals = Vec::with_capacity(args.len());
+                for a in args {
+                    arg_vals.push(self.gen_expr(interner, a));
+                }
+                let name = interner.name(*func);
+                let dst = self.backend.temp();
+                self.backend.emit_call(dst, name, &arg_vals);
+                dst
+            }
+            Expr::BinOp {
+                left, oper, right, ..
+            } => {
+                let l = self.gen_expr(interner, left);
+                let r = self.gen_expr(interner, right);
+                let dst = self.backend.temp();
+                self.backend.emit_binop(dst, oper, l, r);
+                dst
+            }
+            Expr::Record { fields, rtype, .. } => {
+                let mut vals = Vec::with_capacity(fields.len());
+                for (name, e, _) in fields {
+                    vals.push((*name, self.gen_expr(interner, e)));
+                }
+                let mut named = Vec::with_capacity(vals.len());
+                for (sym, v) in &vals {
+                    named.push((interner.name(*sym), *v));
+                }
+                let rname = interner.name(*rtype);
+                let dst = self.backend.temp();
+                self.backend.emit_record(dst, rname, &named);
+                dst
+            }
+            Expr::Seq(items) => {
+                let mut last = None;
+                for (e, _) in items {
+                    last = Some(self.gen_expr(interner, e));
+                }
+                last.unwrap_or_else(|| {
+                    let dst = self.backend.temp();
+                    self.backend.emit_nil(dst);
+                    dst
+                })
+            }
+            Expr::Assign { var, expr, .. } => {
+                let v = self.gen_expr(interner, expr);
+                self.gen_assign(interner, var, v);
+                let dst = self.backend.temp();
+                self.backend.emit_nil(dst);
+                dst
+            }
+            Expr::If {
+                test,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                let cond = self.gen_expr(interner, test);
+                let else_label = self.backend.new_label();
+                let end_label = self.backend.new_label();
+                let dst = self.backend.temp();
+                self.backend.jump_if_false(cond, else_label);
+                let then_val = self.gen_expr(interner, then_branch);
+                self.backend.emit_move(dst, then_val);
+                self.backend.jump(end_label);
+                self.backend.bind_label(else_label);
+                match else_branch {
+                    Some(e) => {
+                        let else_val = self.gen_expr(interner, e);
+                        self.backend.emit_move(dst, else_val);
+                    }
+                    None => self.backend.emit_nil(dst),
+                }
+                self.backend.bind_label(end_label);
+                dst
+            }
+            Expr::While { test, body, .. } => {
+                let start = self.backend.new_label();
+                let end = self.backend.new_label();
+                self.backend.bind_label(start);
+                let cond = self.gen_expr(interner, test);
+                self.backend.jump_if_false(cond, end);
+                self.loop_exits.push(end);
+                self.gen_expr(interner, body);
+                self.loop_exits.pop();
+                self.backend.jump(start);
+                self.backend.bind_label(end);
+                let dst = self.backend.temp();
+                self.backend.emit_nil(dst);
+                dst
+            }
+            Expr::For {
+                var, lo, hi, body, ..
+            } => {
+                let lo_val = self.gen_expr(interner, lo);
+                let hi_val = self.gen_expr(interner, hi);
+                self.scopes.push(HashMap::new());
+                let slot = self.regs.allocate(*var);
+                self.backend.emit_move(slot, lo_val);
+                self.bind(*var, slot);
+
+                let start = self.backend.new_label();
+                let end = self.backend.new_label();
+                self.backend.bind_label(start);
+                let cond = self.backend.temp();
+                self.backend.emit_binop(cond, &Op::Le, slot, hi_val);
+                self.backend.jump_if_false(cond, end);
+                self.loop_exits.push(end);
+                self.gen_expr(interner, body);
+                self.loop_exits.pop();
+
+                let one = self.backend.temp();
+                self.backend.emit_int(one, 1);
+                let next = self.backend.temp();
+                self.backend.emit_binop(next, &Op::Plus, slot, one);
+                self.backend.emit_move(slot, next);
+                self.backend.jump(start);
+                self.backend.bind_label(end);
+                self.pop_scope();
+
+                let dst = self.backend.temp();
+                self.backend.emit_nil(dst);
+                dst
+            }
+            Expr::Break(_) => {
+                let target = *self
+                    .loop_exits
+                    .last()
+                    .expect("semantic analysis should reject `break` outside a loop");
+                self.backend.jump(target);
+                let dst = self.backend.temp();
+                self.backend.emit_nil(dst);
+                dst
+            }
+            Expr::Let { decls, body, .. } => {
+                self.scopes.push(HashMap::new());
+                for d in decls {
+                    self.gen_decl(interner, d);
+                }
+                let v = self.gen_expr(interner, body);
+                self.pop_scope();
+                v
+            }
+            Expr::Array {
+                etype, size, init, ..
+            } => {
+                let size_val = self.gen_expr(interner, size);
+                let init_val = self.gen_expr(interner, init);
+                let ename = interner.name(*etype);
+                let dst = self.backend.temp();
+                self.backend.emit_array(dst, ename, size_val, init_val);
+                dst
+            }
+        }
+    }
+
+    fn gen_decl(&mut self, interner: &mut Interner, decl: &Decl) {
+        match decl {
+            Decl::Var { name, init, .. } => {
+                let v = self.gen_expr(interner, init);
+                let slot = self.regs.allocate(*name);
+                self.backend.emit_move(slot, v);
+                self.bind(*name, slot);
+            }
+            // Types are erased before codegen -- there is nothing to emit.
+            Decl::Type(_) => {}
+            Decl::Function(fundecls) => {
+                for fd in fundecls {
+                    self.gen_fundecl(interner, fd);
+                }
+            }
+        }
+    }
+
+    fn gen_fundecl(&mut self, interner: &mut Interner, fd: &Fundecl) {
+        // `escape` (see the `escape` module) already tracks which bindings
+        // a nested `function` closes over, but nothing downstream of it
+        // acts on that yet: there's no static-link/closure-conversion pass
+        // threading captured bindings into a lifted, flat function. Until
+        // there is, a `function` declared inside another function's body
+        // can't be lowered correctly -- `Backend::enter_function` always
+        // produces a standalone top-level function, with no way to reach
+        // the enclosing function's locals -- so reject it up front instead
+        // of silently emitting code that drops the capture.
+        assert!(
+            self.function_depth == 0,
+            "codegen: function `{}` is declared inside another function's body -- \
+             nested function declarations are not supported",
+            interner.name(fd.name)
+        );
+
+        let name = interner.name(fd.name).to_string();
+        let mut param_names = Vec::with_capacity(fd.params.len());
+        for p in &fd.params {
+            param_names.push(interner.name(p.name).to_string());
+        }
+        let param_refs: Vec<&str> = param_names.iter().map(String::as_str).collect();
+        self.backend.enter_function(&name, &param_refs);
+
+        self.function_depth += 1;
+        self.scopes.push(HashMap::new());
+        for p in &fd.params {
+            let slot = self.regs.allocate(p.name);
+            self.bind(p.name, slot);
+        }
+        let result = self.gen_expr(interner, &fd.body);
+        self.pop_scope();
+        self.function_depth -= 1;
+
+        self.backend.emit_return(result);
+        self.backend.leave_function();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn register_file_spills_to_stack_once_full() {
+        let mut interner = Interner::new();
+        let mut file = RegisterFile::new();
+        let mut values = Vec::new();
+        for i in 0..NUM_REGS + 2 {
+            let sym = interner.symbol(&format!("v{}", i));
+            values.push(file.allocate(sym));
+        }
+        let regs = values.iter().filter(|v| matches!(v, Value::Reg(_))).count();
+        let stack = values.iter().filter(|v| matches!(v, Value::Stack(_))).count();
+        assert_eq!(regs, NUM_REGS);
+        assert_eq!(stack, 2);
+    }
+
+    #[test]
+    fn freed_register_is_reused() {
+        let mut interner = Interner::new();
+        let mut file = RegisterFile::new();
+        let a = interner.symbol("a");
+        let b = interner.symbol("b");
+        let first = file.allocate(a);
+        file.free(first);
+        let second = file.allocate(b);
+        assert_eq!(first, second);
+    }
+}
@@ -0,0 +1,232 @@
+//! A tiny stack/register bytecode backend. There's no loader or interpreter
+//! for this format yet -- `VmBackend` just proves the `Backend` trait can
+//! drive something other than source-to-source C.
+
+use super::{Backend, Label, Value};
+use crate::ast::Op;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instr {
+    LoadInt(Value, i32),
+    LoadString(Value, String),
+    LoadNil(Value),
+    Move(Value, Value),
+    BinOp(Value, Op, Value, Value),
+    Call(Value, String, Vec<Value>),
+    AllocRecord(Value, String, Vec<(String, Value)>),
+    AllocArray(Value, String, Value, Value),
+    LoadSubscript(Value, Value, Value),
+    LoadField(Value, Value, String),
+    StoreSubscript(Value, Value, Value),
+    StoreField(Value, String, Value),
+    Label(usize),
+    Jump(usize),
+    JumpIfFalse(Value, usize),
+    EnterFunction(String, Vec<String>),
+    Return(Value),
+    LeaveFunction,
+}
+
+pub struct VmBackend {
+    instrs: Vec<Instr>,
+    next_label: usize,
+    next_temp: u16,
+}
+
+impl Default for VmBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VmBackend {
+    pub fn new() -> Self {
+        VmBackend {
+            instrs: Vec::new(),
+            next_label: 0,
+            next_temp: 0,
+        }
+    }
+}
+
+impl Backend for VmBackend {
+    type Output = Vec<Instr>;
+
+    // Transient temporaries get their own stack range, disjoint from the
+    // slots `RegisterFile` spills named bindings into (which count down from
+    // -1), so the two never collide.
+    fn temp(&mut self) -> Value {
+        let t = 1000 + self.next_temp as i32;
+        self.next_temp += 1;
+        Value::Stack(-t)
+    }
+
+    fn emit_int(&mut self, dst: Value, n: i32) {
+        self.instrs.push(Instr::LoadInt(dst, n));
+    }
+
+    fn emit_string(&mut self, dst: Value, s: &str) {
+        self.instrs.push(Instr::LoadString(dst, s.to_string()));
+    }
+
+    fn emit_nil(&mut self, dst: Value) {
+        self.instrs.push(Instr::LoadNil(dst));
+    }
+
+    fn emit_move(&mut self, dst: Value, src: Value) {
+        self.instrs.push(Instr::Move(dst, src));
+    }
+
+    fn emit_binop(&mut self, dst: Value, op: &Op, left: Value, right: Value) {
+        self.instrs.push(Instr::BinOp(dst, *op, left, right));
+    }
+
+    fn emit_call(&mut self, dst: Value, func: &str, args: &[Value]) {
+        self.instrs.push(Instr::Call(dst, func.to_string(), args.to_vec()));
+    }
+
+    fn emit_record(&mut self, dst: Value, rtype: &str, fields: &[(&str, Value)]) {
+        let fields = fields.iter().map(|(n, v)| (n.to_string(), *v)).collect();
+        self.instrs.push(Instr::AllocRecord(dst, rtype.to_string(), fields));
+    }
+
+    fn emit_array(&mut self, dst: Value, etype: &str, size: Value, init: Value) {
+        self.instrs.push(Instr::AllocArray(dst, etype.to_string(), size, init));
+    }
+
+    fn emit_subscript(&mut self, dst: Value, array: Value, index: Value) {
+        self.instrs.push(Instr::LoadSubscript(dst, array, index));
+    }
+
+    fn emit_field(&mut self, dst: Value, record: Value, field: &str) {
+        self.instrs.push(Instr::LoadField(dst, record, field.to_string()));
+    }
+
+    fn emit_store_subscript(&mut self, array: Value, index: Value, value: Value) {
+        self.instrs.push(Instr::StoreSubscript(array, index, value));
+    }
+
+    fn emit_store_field(&mut self, record: Value, field: &str, value: Value) {
+        self.instrs.push(Instr::StoreField(record, field.to_string(), value));
+    }
+
+    fn new_label(&mut self) -> Label {
+        let l = Label::new(self.next_label);
+        self.next_label += 1;
+        l
+    }
+
+    fn bind_label(&mut self, label: Label) {
+        self.instrs.push(Instr::Label(label.index()));
+    }
+
+    fn jump(&mut self, label: Label) {
+        self.instrs.push(Instr::Jump(label.index()));
+    }
+
+    fn jump_if_false(&mut self, cond: Value, to: Label) {
+        self.instrs.push(Instr::JumpIfFalse(cond, to.index()));
+    }
+
+    fn enter_function(&mut self, name: &str, params: &[&str]) {
+        let params = params.iter().map(|p| p.to_string()).collect();
+        self.instrs.push(Instr::EnterFunction(name.to_string(), params));
+    }
+
+    fn emit_return(&mut self, v: Value) {
+        self.instrs.push(Instr::Return(v));
+    }
+
+    fn leave_function(&mut self) {
+        self.instrs.push(Instr::LeaveFunction);
+    }
+
+    fn finish(self) -> Vec<Instr> {
+        self.instrs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen::Generator;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn gen_vm(source: &str) -> Vec<Instr> {
+        let mut parser = Parser::new(Lexer::new(source).collect());
+        let expr = parser.parse().expect("source should parse");
+        Generator::new(VmBackend::new()).gen(parser.interner_mut(), &expr)
+    }
+
+    #[test]
+    fn arithmetic_lowers_to_a_flat_instruction_list() {
+        // `gen` wraps the whole program in a synthetic `main`, so the
+        // program's own instructions start right after `EnterFunction`.
+        let instrs = gen_vm("1 + 2 * 3");
+        assert!(matches!(&instrs[0], Instr::EnterFunction(name, params) if name == "main" && params.is_empty()));
+        assert!(matches!(instrs[1], Instr::LoadInt(_, 1)));
+        assert!(matches!(instrs[2], Instr::LoadInt(_, 2)));
+        assert!(matches!(instrs[3], Instr::LoadInt(_, 3)));
+        assert!(matches!(instrs[4], Instr::BinOp(_, Op::Times, _, _)));
+        assert!(matches!(instrs[5], Instr::BinOp(_, Op::Plus, _, _)));
+        assert!(matches!(instrs.last(), Some(Instr::LeaveFunction)));
+    }
+
+    #[test]
+    fn while_loop_emits_a_backward_and_a_forward_jump() {
+        let instrs = gen_vm("while 1 do 2");
+        let labels: Vec<usize> = instrs
+            .iter()
+            .filter_map(|i| match i {
+                Instr::Label(n) => Some(*n),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(labels, vec![0, 1]);
+        assert!(instrs.iter().any(|i| matches!(i, Instr::Jump(0))));
+        assert!(instrs.iter().any(|i| matches!(i, Instr::JumpIfFalse(_, 1))));
+    }
+
+    #[test]
+    fn function_declarations_bracket_their_body() {
+        let instrs = gen_vm("let function f(x: int): int = x + 1 in f(1) end");
+        assert!(instrs
+            .iter()
+            .any(|i| matches!(i, Instr::EnterFunction(name, params) if name == "f" && params == &["x"])));
+        assert!(
+            instrs
+                .iter()
+                .filter(|i| matches!(i, Instr::LeaveFunction))
+                .count()
+                == 2,
+            "both `f` and the synthetic `main` wrapper should close"
+        );
+        assert!(instrs.iter().any(|i| matches!(i, Instr::Call(_, name, _) if name == "f")));
+    }
+
+    #[test]
+    fn function_body_returns_its_last_value() {
+        let instrs = gen_vm("let function f(x: int): int = x + 1 in f(1) end");
+        let enter_f = instrs
+            .iter()
+            .position(|i| matches!(i, Instr::EnterFunction(name, _) if name == "f"))
+            .unwrap();
+        let leave_f = instrs[enter_f..]
+            .iter()
+            .position(|i| matches!(i, Instr::LeaveFunction))
+            .unwrap()
+            + enter_f;
+        assert!(matches!(instrs[leave_f - 1], Instr::Return(_)));
+    }
+
+    #[test]
+    #[should_panic(expected = "nested function declarations are not supported")]
+    fn nested_function_declarations_are_rejected() {
+        gen_vm(
+            "let function outer(): int = \
+               let function inner(): int = 1 in inner() end \
+             in outer() end",
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use crate::lexer::TokenPos;
+
+/// A single rendered-ready diagnostic: a source span and a human message.
+pub struct Diagnostic {
+    pub span: TokenPos,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(span: TokenPos, message: impl Into<String>) -> Self {
+        Diagnostic {
+            span,
+            message: message.into(),
+        }
+    }
+
+    /// Render the source line containing the span with a `^^^` underline under
+    /// `source[start..end]`, followed by the message. A zero-width span (e.g.
+    /// end of input) still gets a single caret.
+    pub fn render(&self, source: &str) -> String {
+        let start = self.span.start().min(source.len());
+        let end = self.span.end().min(source.len()).max(start);
+        let line_start = source[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let line_end = source[start..]
+            .find('\n')
+            .map(|i| start + i)
+            .unwrap_or(source.len());
+        let line = &source[line_start..line_end];
+        let pad = start - line_start;
+        let width = (end - start).max(1);
+        format!(
+            "{}\n{}{} {}",
+            line,
+            " ".repeat(pad),
+            "^".repeat(width),
+            self.message
+        )
+    }
+}
+
+/// A collector that accumulates diagnostics produced across a single pass.
+#[derive(Default)]
+pub struct Diagnostics {
+    items: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Diagnostics { items: vec![] }
+    }
+
+    pub fn push(&mut self, diag: Diagnostic) {
+        self.items.push(diag);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Diagnostic> {
+        self.items.iter()
+    }
+
+    /// Render every diagnostic against `source`, separated by blank lines.
+    pub fn render(&self, source: &str) -> String {
+        self.items
+            .iter()
+            .map(|d| d.render(source))
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caret_underlines_span() {
+        let source = "foo ~ bar";
+        let diag = Diagnostic::new(TokenPos::new(4, 5), "unexpected character `~`");
+        assert_eq!(
+            diag.render(source),
+            "foo ~ bar\n    ^ unexpected character `~`"
+        );
+    }
+
+    #[test]
+    fn collector_accumulates() {
+        let mut diags = Diagnostics::new();
+        assert!(diags.is_empty());
+        diags.push(Diagnostic::new(TokenPos::new(0, 1), "boom"));
+        diags.push(Diagnostic::new(TokenPos::new(2, 3), "bang"));
+        assert_eq!(diags.len(), 2);
+    }
+}
@@ -0,0 +1,269 @@
+//! Escape analysis: walks a parsed tree once, tracking lexical
+//! function-nesting depth, and marks every variable/parameter binding that
+//! is read or written from inside a nested `function` as escaping. The
+//! pass runs after parsing and before codegen, so a backend can later
+//! decide whether a binding lives in a register or must be spilled to a
+//! stack frame.
+
+use std::collections::HashMap;
+
+use crate::ast::{Decl, Expr, Fundecl, Var};
+use crate::intern::Symbol;
+
+/// Analyze `expr` in place, setting the `escape` cell of every binding that
+/// is captured by an inner function.
+pub fn analyze(expr: &Expr) {
+    let mut walker = Walker {
+        scopes: vec![HashMap::new()],
+    };
+    walker.visit_expr(expr, 0);
+}
+
+/// Bindings in scope, innermost last, each recording the nesting depth it
+/// was declared at and the `escape` cell to flip if it's captured.
+struct Walker<'t> {
+    scopes: Vec<HashMap<Symbol, (u32, &'t std::cell::Cell<bool>)>>,
+}
+
+impl<'t> Walker<'t> {
+    fn bind(&mut self, name: Symbol, depth: u32, cell: &'t std::cell::Cell<bool>) {
+        self.scopes.last_mut().unwrap().insert(name, (depth, cell));
+    }
+
+    /// A use of `name` at `depth` escapes its binding if `depth` is
+    /// strictly greater than the depth it was declared at -- i.e. an inner
+    /// `Fundecl` is the one doing the reading/writing.
+    fn touch(&self, name: Symbol, depth: u32) {
+        for scope in self.scopes.iter().rev() {
+            if let Some((decl_depth, cell)) = scope.get(&name) {
+                if depth > *decl_depth {
+                    cell.set(true);
+                }
+                return;
+            }
+        }
+    }
+
+    fn visit_var(&mut self, var: &'t Var, depth: u32) {
+        match var {
+            Var::Simple(name, _) => self.touch(*name, depth),
+            Var::Field(base, _, _) => self.visit_var(base, depth),
+            Var::Subscript(base, index, _) => {
+                self.visit_var(base, depth);
+                self.visit_expr(index, depth);
+            }
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &'t Expr, depth: u32) {
+        match expr {
+            Expr::Nil | Expr::Int(_) | Expr::String(_) | Expr::Break(_) => {}
+            Expr::VarRef(var) => self.visit_var(var, depth),
+            Expr::Call { args, .. } => {
+                for arg in args {
+                    self.visit_expr(arg, depth);
+                }
+            }
+            Expr::BinOp { left, right, .. } => {
+                self.visit_expr(left, depth);
+                self.visit_expr(right, depth);
+            }
+            Expr::Record { fields, .. } => {
+                for (_, field_expr, _) in fields {
+                    self.visit_expr(field_expr, depth);
+                }
+            }
+            Expr::Seq(items) => {
+                for (item, _) in items {
+                    self.visit_expr(item, depth);
+                }
+            }
+            Expr::Assign { var, expr, .. } => {
+                self.visit_var(var, depth);
+                self.visit_expr(expr, depth);
+            }
+            Expr::If {
+                test,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.visit_expr(test, depth);
+                self.visit_expr(then_branch, depth);
+                if let Some(e) = else_branch {
+                    self.visit_expr(e, depth);
+                }
+            }
+            Expr::While { test, body, .. } => {
+                self.visit_expr(test, depth);
+                self.visit_expr(body, depth);
+            }
+            Expr::For {
+                var,
+                escape,
+                lo,
+                hi,
+                body,
+                ..
+            } => {
+                self.visit_expr(lo, depth);
+                self.visit_expr(hi, depth);
+                self.scopes.push(HashMap::new());
+                self.bind(*var, depth, escape);
+                self.visit_expr(body, depth);
+                self.scopes.pop();
+            }
+            Expr::Let { decls, body, .. } => {
+                self.scopes.push(HashMap::new());
+                for decl in decls {
+                    self.visit_decl(decl, depth);
+                }
+                self.visit_expr(body, depth);
+                self.scopes.pop();
+            }
+            Expr::Array { size, init, .. } => {
+                self.visit_expr(size, depth);
+                self.visit_expr(init, depth);
+            }
+        }
+    }
+
+    fn visit_decl(&mut self, decl: &'t Decl, depth: u32) {
+        match decl {
+            Decl::Var {
+                name, escape, init, ..
+            } => {
+                self.visit_expr(init, depth);
+                self.bind(*name, depth, escape);
+            }
+            Decl::Type(_) => {}
+            Decl::Function(group) => {
+                for fundecl in group {
+                    self.visit_fundecl(fundecl, depth);
+                }
+            }
+        }
+    }
+
+    /// A function's own body is one nesting level deeper than the scope it
+    /// was declared in -- its parameters are bound at that deeper level, so
+    /// uses within its own body don't count as escaping, but uses from a
+    /// function nested inside it do.
+    fn visit_fundecl(&mut self, fundecl: &'t Fundecl, depth: u32) {
+        let inner = depth + 1;
+        self.scopes.push(HashMap::new());
+        for param in &fundecl.params {
+            self.bind(param.name, inner, &param.escape);
+        }
+        self.visit_expr(&fundecl.body, inner);
+        self.scopes.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse_source(source: &str) -> Expr {
+        let mut parser = Parser::new(Lexer::new(source).collect());
+        parser.parse().expect("source should parse")
+    }
+
+    #[test]
+    fn loop_variable_used_only_locally_does_not_escape() {
+        let expr = parse_source("for i := 1 to 10 do print(\"x\")");
+        analyze(&expr);
+        match &expr {
+            Expr::For { escape, .. } => assert!(!escape.get()),
+            _ => panic!("expected a for loop"),
+        }
+    }
+
+    #[test]
+    fn variable_closed_over_by_nested_function_escapes() {
+        let expr = parse_source(
+            "let
+                var x := 10
+                function f() = print(x)
+             in
+                f()
+             end",
+        );
+        analyze(&expr);
+        match &expr {
+            Expr::Let { decls, .. } => match decls[0].as_ref() {
+                Decl::Var { escape, .. } => assert!(escape.get()),
+                _ => panic!("expected a var decl"),
+            },
+            _ => panic!("expected a let"),
+        }
+    }
+
+    #[test]
+    fn variable_used_only_in_its_own_scope_does_not_escape() {
+        let expr = parse_source(
+            "let
+                var x := 10
+             in
+                x + 1
+             end",
+        );
+        analyze(&expr);
+        match &expr {
+            Expr::Let { decls, .. } => match decls[0].as_ref() {
+                Decl::Var { escape, .. } => assert!(!escape.get()),
+                _ => panic!("expected a var decl"),
+            },
+            _ => panic!("expected a let"),
+        }
+    }
+
+    #[test]
+    fn parameter_closed_over_by_nested_function_escapes() {
+        let expr = parse_source(
+            "let
+                function outer(x: int) =
+                    let
+                        function inner() = print(x)
+                    in
+                        inner()
+                    end
+             in
+                outer(1)
+             end",
+        );
+        analyze(&expr);
+        match &expr {
+            Expr::Let { decls, .. } => match decls[0].as_ref() {
+                Decl::Function(group) => {
+                    assert!(group[0].params[0].escape.get());
+                }
+                _ => panic!("expected a function decl"),
+            },
+            _ => panic!("expected a let"),
+        }
+    }
+
+    #[test]
+    fn parameter_used_only_in_its_own_function_does_not_escape() {
+        let expr = parse_source(
+            "let
+                function f(x: int) = print(\"x\")
+             in
+                f(1)
+             end",
+        );
+        analyze(&expr);
+        match &expr {
+            Expr::Let { decls, .. } => match decls[0].as_ref() {
+                Decl::Function(group) => {
+                    assert!(!group[0].params[0].escape.get());
+                }
+                _ => panic!("expected a function decl"),
+            },
+            _ => panic!("expected a let"),
+        }
+    }
+}
@@ -1,39 +1,38 @@
 use std::collections::HashMap;
+use std::rc::Rc;
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Symbol {
     idx: usize,
 }
 
-pub struct Interner<'a> {
-    entries: HashMap<&'a str, Symbol>,
-    names: Vec<&'a str>,
+/// Interns strings into cheap, `Copy` [`Symbol`]s. Owns its backing storage
+/// (shared via `Rc` between the lookup table and the name list), so symbols
+/// stay valid for as long as the interner itself -- nothing is leaked.
+pub struct Interner {
+    entries: HashMap<Rc<str>, Symbol>,
+    names: Vec<Rc<str>>,
 }
 
-// todo: this works, but can we do this without leaking?
-fn intern(name: &str) -> &'static str {
-    Box::leak(Box::new(name.to_string()))
-}
-
-impl<'a> Interner<'a> {
+impl Interner {
     pub fn new() -> Self {
-	Interner { entries: HashMap::new(), names: vec![] }
+        Interner { entries: HashMap::new(), names: vec![] }
     }
 
-    pub fn symbol<'b>(&mut self, name: &'b str) -> Symbol {
-	if let Some(sym) = self.entries.get(name) {
-	    return *sym;
-	}
-	let interned = intern(name);
-	let idx = self.names.len();
-	let sym = Symbol{idx};
-	self.names.push(interned);
-	self.entries.insert(interned, sym);
-	sym
+    pub fn symbol(&mut self, name: &str) -> Symbol {
+        if let Some(sym) = self.entries.get(name) {
+            return *sym;
+        }
+        let rc: Rc<str> = Rc::from(name);
+        let idx = self.names.len();
+        let sym = Symbol{idx};
+        self.names.push(rc.clone());
+        self.entries.insert(rc, sym);
+        sym
     }
 
-    pub fn name(&mut self, sym: Symbol) -> &'a str {
-	&self.names[sym.idx]
+    pub fn name(&self, sym: Symbol) -> &str {
+        &self.names[sym.idx]
     }
 }
 
@@ -56,12 +55,24 @@ mod tests {
     }
 
     #[test]
-    fn mixed_lifetimes() {
+    fn interning_an_owned_temporary_does_not_borrow_it() {
 	let mut tbl = Interner::new();
 
 	let s1 = tbl.symbol("foo");
-	let s2 = tbl.symbol("foo".to_string().as_str());
+	let s2 = tbl.symbol(String::from("foo").as_str());
 
 	assert_eq!(s1, s2);
+	assert_eq!(tbl.name(s1), "foo");
+    }
+
+    #[test]
+    fn name_does_not_require_exclusive_access() {
+	let mut tbl = Interner::new();
+	let s1 = tbl.symbol("foo");
+	let s2 = tbl.symbol("bar");
+
+	// Both names can be read back through shared references at once --
+	// `name` no longer needs `&mut self`.
+	assert_eq!((tbl.name(s1), tbl.name(s2)), ("foo", "bar"));
     }
 }
@@ -1,6 +1,29 @@
 use std::{iter::Peekable, str::CharIndices};
 
+/// Why the lexer could not turn a span of source into a token. The offending
+/// span travels on the surrounding [`Token`]'s [`TokenPos`].
 #[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LexError {
+    UnexpectedChar(char),
+    UnterminatedString,
+    UnterminatedComment,
+    MalformedEscapeSequence,
+    MalformedNumber,
+}
+
+impl LexError {
+    pub fn message(&self) -> String {
+        match self {
+            LexError::UnexpectedChar(c) => format!("unexpected character `{}`", c),
+            LexError::UnterminatedString => "unterminated string literal".to_string(),
+            LexError::UnterminatedComment => "unterminated block comment".to_string(),
+            LexError::MalformedEscapeSequence => "malformed escape sequence".to_string(),
+            LexError::MalformedNumber => "integer literal out of range".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum TokenKind<'source> {
     // symbols
     Comma,
@@ -49,10 +72,12 @@ pub enum TokenKind<'source> {
     // other
     Id(&'source str),
     Num(i32),
-    String(&'source str),
+    // Decoded at lex time, so this holds the string's real contents, not
+    // the raw source bytes between the quotes.
+    String(String),
     // exceptional
     EOF,
-    Error,
+    Error(LexError),
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -61,6 +86,20 @@ pub struct TokenPos {
     end: usize,
 }
 
+impl TokenPos {
+    pub fn new(start: usize, end: usize) -> Self {
+        TokenPos { start, end }
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Token<'source> {
     pub kind: TokenKind<'source>,
@@ -72,7 +111,6 @@ pub struct Lexer<'source> {
     iter: Peekable<CharIndices<'source>>,
     c: char,   // current char
     ci: usize, // current offset
-    error: bool,
 }
 
 impl<'source> Lexer<'source> {
@@ -82,14 +120,15 @@ impl<'source> Lexer<'source> {
             iter: input.char_indices().peekable(),
             c: '\x00',
             ci: 0,
-            error: false,
         };
         lex.scan_char();
         lex
     }
 
     pub fn next_token(&mut self) -> Token<'source> {
-        self.scan_whitespace();
+        if let Some(tok) = self.skip_trivia() {
+            return tok;
+        }
         if self.is_at_end() {
             return Token {
                 kind: TokenKind::EOF,
@@ -147,25 +186,25 @@ impl<'source> Lexer<'source> {
             '"' => {
                 return self.scan_quote();
             }
-            // this is a hack...
-            _ => TokenKind::Error,
+            _ => {
+                // Not a single-character symbol: dispatch on the longer forms.
+                if self.c.is_alphabetic() {
+                    return self.scan_identifier_or_keyword();
+                } else if self.c.is_digit(10) {
+                    return self.scan_number();
+                } else {
+                    return self.error_token();
+                }
+            }
         };
 
-        if kind != TokenKind::Error {
-            self.scan_char();
-            Token {
-                kind,
-                pos: TokenPos {
-                    start,
-                    end: self.ci,
-                },
-            }
-        } else if self.c.is_alphabetic() {
-            self.scan_identifier_or_keyword()
-        } else if self.c.is_digit(10) {
-            self.scan_number()
-        } else {
-            self.error_token()
+        self.scan_char();
+        Token {
+            kind,
+            pos: TokenPos {
+                start,
+                end: self.ci,
+            },
         }
     }
 
@@ -183,37 +222,158 @@ impl<'source> Lexer<'source> {
         }
     }
 
-    fn scan_whitespace(&mut self) {
-        while self.c == ' ' || self.c == '\t' || self.c == '\r' || self.c == '\n' {
-            self.scan_char();
+    /// Skip whitespace and `/* ... */` comments, which may nest and may
+    /// interleave with each other. Returns an error token only if a comment
+    /// runs off the end of the source; whitespace never fails.
+    fn skip_trivia(&mut self) -> Option<Token<'source>> {
+        loop {
+            while self.c == ' ' || self.c == '\t' || self.c == '\r' || self.c == '\n' {
+                self.scan_char();
+            }
+            if self.c == '/' && self.peek_char() == Some('*') {
+                if let Some((err, pos)) = self.scan_comment() {
+                    return Some(Token {
+                        kind: TokenKind::Error(err),
+                        pos,
+                    });
+                }
+                continue;
+            }
+            return None;
         }
     }
 
+    fn peek_char(&mut self) -> Option<char> {
+        self.iter.peek().map(|(_, c)| *c)
+    }
+
+    /// Consume a `/* ... */` comment, including any nested ones, tracking
+    /// depth so `/* /* */ */` closes only at the outer `*/`. Called with
+    /// the cursor on the opening `/`.
+    fn scan_comment(&mut self) -> Option<(LexError, TokenPos)> {
+        let start = self.ci;
+        let mut depth = 0u32;
+        loop {
+            if self.is_at_end() {
+                return Some((LexError::UnterminatedComment, TokenPos::new(start, self.ci)));
+            }
+            if self.c == '/' && self.peek_char() == Some('*') {
+                self.scan_char();
+                self.scan_char();
+                depth += 1;
+            } else if self.c == '*' && self.peek_char() == Some('/') {
+                self.scan_char();
+                self.scan_char();
+                depth -= 1;
+                if depth == 0 {
+                    return None;
+                }
+            } else {
+                self.scan_char();
+            }
+        }
+    }
+
+    /// Scan a string literal, decoding `\n \t \" \\`, `\ddd` decimal escapes,
+    /// and the `\<whitespace>\` line-continuation form into its real
+    /// contents. A malformed escape is reported with a span over just that
+    /// escape, but scanning still runs to the closing quote (or EOF) so the
+    /// rest of the source keeps lexing.
     fn scan_quote(&mut self) -> Token<'source> {
         let start = self.ci;
-        let mut prev = self.c;
-        self.scan_char(); // eat first quote
+        self.scan_char(); // eat the opening quote
+        let mut content = String::new();
+        let mut error: Option<(LexError, TokenPos)> = None;
         loop {
-            if self.is_at_end() || (self.c == '"' && prev != '\\') {
-                break;
+            if self.is_at_end() {
+                return Token {
+                    kind: TokenKind::Error(LexError::UnterminatedString),
+                    pos: TokenPos::new(start, self.ci),
+                };
+            }
+            match self.c {
+                '"' => break,
+                '\\' => {
+                    let escape_start = self.ci;
+                    self.scan_char(); // eat the backslash
+                    match self.c {
+                        'n' => {
+                            content.push('\n');
+                            self.scan_char();
+                        }
+                        't' => {
+                            content.push('\t');
+                            self.scan_char();
+                        }
+                        '"' => {
+                            content.push('"');
+                            self.scan_char();
+                        }
+                        '\\' => {
+                            content.push('\\');
+                            self.scan_char();
+                        }
+                        c if c.is_ascii_digit() => {
+                            let mut digits = String::new();
+                            while digits.len() < 3 && self.c.is_ascii_digit() {
+                                digits.push(self.c);
+                                self.scan_char();
+                            }
+                            let code = (digits.len() == 3)
+                                .then(|| digits.parse::<u16>().unwrap())
+                                .filter(|n| *n <= 255);
+                            match code {
+                                Some(n) => content.push(n as u8 as char),
+                                None => {
+                                    error.get_or_insert((
+                                        LexError::MalformedEscapeSequence,
+                                        TokenPos::new(escape_start, self.ci),
+                                    ));
+                                }
+                            }
+                        }
+                        c if c.is_whitespace() => {
+                            while self.c.is_whitespace() {
+                                self.scan_char();
+                            }
+                            if self.c == '\\' {
+                                self.scan_char();
+                            } else {
+                                error.get_or_insert((
+                                    LexError::MalformedEscapeSequence,
+                                    TokenPos::new(escape_start, self.ci),
+                                ));
+                            }
+                        }
+                        _ => {
+                            self.scan_char(); // consume the invalid escape char
+                            error.get_or_insert((
+                                LexError::MalformedEscapeSequence,
+                                TokenPos::new(escape_start, self.ci),
+                            ));
+                        }
+                    }
+                }
+                c => {
+                    content.push(c);
+                    self.scan_char();
+                }
             }
-            prev = self.c;
-            self.scan_char();
         }
-        if self.c != '"' {
-            self.error_token()
-        } else {
-            self.scan_char();
-            let end = self.ci;
-            Token {
-                kind: TokenKind::String(&self.input[(start + 1)..(end - 1)]),
+        self.scan_char(); // eat the closing quote
+        let end = self.ci;
+        match error {
+            Some((kind, pos)) => Token {
+                kind: TokenKind::Error(kind),
+                pos,
+            },
+            None => Token {
+                kind: TokenKind::String(content),
                 pos: TokenPos { start, end },
-            }
+            },
         }
     }
 
-    // todo: comments
-
     fn scan_identifier_or_keyword(&mut self) -> Token<'source> {
         let start = self.ci;
         while self.c.is_alphanumeric() || self.c == '_' {
@@ -257,20 +417,29 @@ impl<'source> Lexer<'source> {
             self.scan_char();
         }
         let end = self.ci;
-        let num = self.input[start..end].parse::<i32>().unwrap();
-        Token {
-            kind: TokenKind::Num(num),
-            pos: TokenPos { start, end },
+        let pos = TokenPos { start, end };
+        match self.input[start..end].parse::<i32>() {
+            Ok(num) => Token {
+                kind: TokenKind::Num(num),
+                pos,
+            },
+            // e.g. an integer literal that overflows `i32`.
+            Err(_) => Token {
+                kind: TokenKind::Error(LexError::MalformedNumber),
+                pos,
+            },
         }
     }
 
     fn error_token(&mut self) -> Token<'source> {
-        self.error = true;
+        let start = self.ci;
+        let c = self.c;
+        self.scan_char(); // consume the offending char so lexing can continue
         Token {
-            kind: TokenKind::Error,
+            kind: TokenKind::Error(LexError::UnexpectedChar(c)),
             pos: TokenPos {
-                start: self.ci,
-                end: self.ci + 1,
+                start,
+                end: self.ci,
             },
         }
     }
@@ -278,10 +447,9 @@ impl<'source> Lexer<'source> {
 
 impl<'source> Iterator for Lexer<'source> {
     type Item = Token<'source>;
+    // Errors are yielded as `Error` tokens and scanning continues, so a caller
+    // can collect every diagnostic in a single pass.
     fn next(&mut self) -> Option<Self::Item> {
-        if self.error {
-            return None;
-        }
         let tok = self.next_token();
         if tok.kind == TokenKind::EOF {
             None
@@ -334,10 +502,29 @@ mod tests {
 
     #[test]
     fn next_token_with_error() {
+        // An unexpected character is reported, then scanning resumes.
         let mut lex = Lexer::new("foo ~ bar");
         assert_tok!(lex.next_token(), TokenKind::Id("foo"), 0, 3);
-        assert_tok!(lex.next_token(), TokenKind::Error, 4, 5);
-        assert_tok!(lex.next_token(), TokenKind::Error, 4, 5);
+        assert_tok!(
+            lex.next_token(),
+            TokenKind::Error(LexError::UnexpectedChar('~')),
+            4,
+            5
+        );
+        assert_tok!(lex.next_token(), TokenKind::Id("bar"), 6, 9);
+    }
+
+    #[test]
+    fn errors_keep_flowing() {
+        // Several bad characters each yield their own diagnostic in one pass.
+        let toks: Vec<Token> = Lexer::new("1 ~ 2 @ 3").collect();
+        let errors: Vec<_> = toks
+            .iter()
+            .filter(|t| matches!(t.kind, TokenKind::Error(_)))
+            .collect();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(toks.first().unwrap().kind, TokenKind::Num(1));
+        assert_eq!(toks.last().unwrap().kind, TokenKind::Num(3));
     }
 
     #[test]
@@ -388,4 +575,86 @@ mod tests {
         assert_tok!(lex.next_token(), TokenKind::Equals);
         assert_tok!(lex.next_token(), TokenKind::NotEquals);
     }
+
+    #[test]
+    fn string_with_simple_escapes() {
+        let mut lex = Lexer::new(r#""a\nb\tc\"d\\e""#);
+        assert_tok!(
+            lex.next_token(),
+            TokenKind::String("a\nb\tc\"d\\e".to_string())
+        );
+    }
+
+    #[test]
+    fn string_with_decimal_escape() {
+        // \065 is 'A'.
+        let mut lex = Lexer::new(r#""\065""#);
+        assert_tok!(lex.next_token(), TokenKind::String("A".to_string()));
+    }
+
+    #[test]
+    fn string_with_line_continuation() {
+        // A backslash-whitespace-backslash run is swallowed with no output.
+        let mut lex = Lexer::new("\"a\\   \n   \\b\"");
+        assert_tok!(lex.next_token(), TokenKind::String("ab".to_string()));
+    }
+
+    #[test]
+    fn string_with_malformed_decimal_escape() {
+        // \9 isn't three digits, so it's reported with a span over just the escape.
+        let mut lex = Lexer::new(r#""a\9b""#);
+        assert_tok!(
+            lex.next_token(),
+            TokenKind::Error(LexError::MalformedEscapeSequence),
+            2,
+            4
+        );
+    }
+
+    #[test]
+    fn string_with_unknown_escape_letter() {
+        let mut lex = Lexer::new(r#""a\qb""#);
+        assert_tok!(
+            lex.next_token(),
+            TokenKind::Error(LexError::MalformedEscapeSequence),
+            2,
+            4
+        );
+    }
+
+    #[test]
+    fn nested_block_comment_is_skipped_whole() {
+        let mut lex = Lexer::new("/* outer /* inner */ still outer */ foo");
+        assert_tok!(lex.next_token(), TokenKind::Id("foo"), 36, 39);
+    }
+
+    #[test]
+    fn unterminated_nested_comment() {
+        let mut lex = Lexer::new("/* outer /* inner */ foo");
+        assert_tok!(
+            lex.next_token(),
+            TokenKind::Error(LexError::UnterminatedComment),
+            0,
+            24
+        );
+    }
+
+    #[test]
+    fn number_overflowing_i32_is_malformed() {
+        // One past i32::MAX.
+        let mut lex = Lexer::new("2147483648");
+        assert_tok!(
+            lex.next_token(),
+            TokenKind::Error(LexError::MalformedNumber),
+            0,
+            10
+        );
+    }
+
+    #[test]
+    fn comment_interleaved_with_whitespace() {
+        let mut lex = Lexer::new("  /* skip me */  foo  /* and me */  bar");
+        assert_tok!(lex.next_token(), TokenKind::Id("foo"), 17, 20);
+        assert_tok!(lex.next_token(), TokenKind::Id("bar"), 36, 39);
+    }
 }
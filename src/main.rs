@@ -1,7 +1,11 @@
 pub mod ast;
+pub mod codegen;
+pub mod diag;
+pub mod escape;
 pub mod intern;
 pub mod lexer;
 pub mod parser;
+pub mod semant;
 
 fn main() {
     let source = "
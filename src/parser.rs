@@ -1,128 +1,803 @@
-use std::iter::Peekable;
+use std::cell::Cell;
 
-use crate::lexer::{Lexer, Token, TokenKind, TokenPos};
-use crate::ast::{Expr, Op};
-
-
-/*
- * S -> E / 
- *
- *
- */
+use crate::ast::{Decl, Expr, Field, Fundecl, Op, Ty, Typedecl, Var};
+use crate::diag::Diagnostic;
+use crate::intern::{Interner, Symbol};
+use crate::lexer::{Token, TokenKind, TokenPos};
 
 pub struct Parser<'a> {
     tokens: Vec<Token<'a>>,
     pos: usize,
+    interner: Interner,
 }
 
 #[derive(Debug, PartialEq)]
 pub enum ParseError<'a> {
-    UnexpectedToken(&'static str, TokenKind<'a>),
+    UnexpectedToken {
+        expected: &'static str,
+        found: TokenKind<'a>,
+        pos: TokenPos,
+    },
+    UnexpectedEof {
+        expected: &'static str,
+    },
+    MissingDelimiter {
+        delimiter: &'static str,
+        pos: TokenPos,
+    },
 }
 
 type ParseResult<'a, T> = Result<T, ParseError<'a>>;
 
-fn unexpected_token<'a>(expected: &'static str, actual: TokenKind<'a>) -> ParseResult<'a, Expr<'a>> {
-    Err(ParseError::UnexpectedToken(expected, actual))
+impl<'a> ParseError<'a> {
+    pub fn message(&self) -> String {
+        match self {
+            ParseError::UnexpectedToken {
+                expected, found, ..
+            } => format!("expected {}, found {:?}", expected, found),
+            ParseError::UnexpectedEof { expected } => {
+                format!("expected {}, found end of input", expected)
+            }
+            ParseError::MissingDelimiter { delimiter, .. } => format!("missing `{}`", delimiter),
+        }
+    }
+
+    pub fn pos(&self) -> Option<TokenPos> {
+        match self {
+            ParseError::UnexpectedToken { pos, .. } => Some(*pos),
+            ParseError::MissingDelimiter { pos, .. } => Some(*pos),
+            ParseError::UnexpectedEof { .. } => None,
+        }
+    }
+
+    /// Build a renderable diagnostic, anchoring an end-of-input error at the
+    /// end of the source.
+    pub fn to_diagnostic(&self, source: &str) -> Diagnostic {
+        let span = self
+            .pos()
+            .unwrap_or_else(|| TokenPos::new(source.len(), source.len()));
+        Diagnostic::new(span, self.message())
+    }
+}
+
+/// An infix operator as seen by the precedence-climbing core. Most operators
+/// fold into an `Expr::BinOp`, but Tiger's `&`/`|` have no AST node of their
+/// own: they desugar to `if`, matching the semantics in Appel's grammar.
+enum InfixOp {
+    Bin(Op),
+    And,
+    Or,
+}
+
+fn infix_op(kind: TokenKind) -> Option<InfixOp> {
+    Some(match kind {
+        TokenKind::Times => InfixOp::Bin(Op::Times),
+        TokenKind::Divide => InfixOp::Bin(Op::Divide),
+        TokenKind::Plus => InfixOp::Bin(Op::Plus),
+        TokenKind::Minus => InfixOp::Bin(Op::Minus),
+        TokenKind::Equals => InfixOp::Bin(Op::Eq),
+        TokenKind::NotEquals => InfixOp::Bin(Op::Neq),
+        TokenKind::LT => InfixOp::Bin(Op::Lt),
+        TokenKind::LE => InfixOp::Bin(Op::Le),
+        TokenKind::GT => InfixOp::Bin(Op::Gt),
+        TokenKind::GE => InfixOp::Bin(Op::Ge),
+        TokenKind::Ampsersand => InfixOp::And,
+        TokenKind::Pipe => InfixOp::Or,
+        _ => return None,
+    })
+}
+
+/// Left binding power. Higher binds tighter: `*`/`/` > `+`/`-` > comparisons
+/// > `&` > `|`. Comparisons are non-associative and handled specially below.
+fn left_bp(op: &InfixOp) -> u8 {
+    match op {
+        InfixOp::Bin(Op::Times | Op::Divide) => 9,
+        InfixOp::Bin(Op::Plus | Op::Minus) => 7,
+        InfixOp::Bin(Op::Eq | Op::Neq | Op::Lt | Op::Le | Op::Gt | Op::Ge) => 5,
+        InfixOp::And => 3,
+        InfixOp::Or => 1,
+    }
+}
+
+fn is_comparison(op: &InfixOp) -> bool {
+    matches!(
+        op,
+        InfixOp::Bin(Op::Eq | Op::Neq | Op::Lt | Op::Le | Op::Gt | Op::Ge)
+    )
 }
 
+/// Binding power of unary minus, above every infix operator so `-a*b` parses
+/// as `(-a)*b`.
+const PREFIX_BP: u8 = 11;
 
 impl<'a> Parser<'a> {
     pub fn new(tokens: Vec<Token<'a>>) -> Self {
-	Parser {
-	    tokens,
-	    pos: 0,
-	}
+        Parser {
+            tokens,
+            pos: 0,
+            interner: Interner::new(),
+        }
+    }
+
+    /// The interner that produced every `Symbol` in the parsed tree --
+    /// later passes resolve names against this same table.
+    pub fn interner_mut(&mut self) -> &mut Interner {
+        &mut self.interner
     }
 
     fn is_eof(&self) -> bool {
-	self.pos >= self.tokens.len()
+        self.pos >= self.tokens.len()
     }
 
     fn peek(&self) -> TokenKind<'a> {
-	println!("{}", self.pos);
-	self.tokens[self.pos].kind
+        if self.is_eof() {
+            TokenKind::EOF
+        } else {
+            self.tokens[self.pos].kind.clone()
+        }
     }
 
     fn tok_pos(&self) -> TokenPos {
-	self.tokens[self.pos].pos
+        let i = self.pos.min(self.tokens.len().saturating_sub(1));
+        self.tokens[i].pos
     }
 
     fn is_match(&self, kind: TokenKind) -> bool {
-	!self.is_eof() && self.peek() == kind
+        !self.is_eof() && self.peek() == kind
     }
 
     fn advance(&mut self) {
-	self.pos += 1;
+        self.pos += 1;
+    }
+
+    /// Construct an error against the current token: end-of-input folds into
+    /// [`ParseError::UnexpectedEof`] automatically.
+    fn err<T>(&self, expected: &'static str) -> ParseResult<'a, T> {
+        if self.is_eof() {
+            Err(ParseError::UnexpectedEof { expected })
+        } else {
+            Err(ParseError::UnexpectedToken {
+                expected,
+                found: self.peek(),
+                pos: self.tok_pos(),
+            })
+        }
+    }
+
+    fn eat(&mut self, kind: TokenKind<'a>, desc: &'static str) -> ParseResult<'a, ()> {
+        if self.is_match(kind) {
+            self.advance();
+            Ok(())
+        } else {
+            self.err(desc)
+        }
+    }
+
+    /// Like [`eat`], but a missing closing delimiter is reported as
+    /// [`ParseError::MissingDelimiter`]; at end of input it is still
+    /// [`ParseError::UnexpectedEof`].
+    fn eat_close(&mut self, kind: TokenKind<'a>, delim: &'static str) -> ParseResult<'a, ()> {
+        if self.is_match(kind) {
+            self.advance();
+            Ok(())
+        } else if self.is_eof() {
+            Err(ParseError::UnexpectedEof { expected: delim })
+        } else {
+            Err(ParseError::MissingDelimiter {
+                delimiter: delim,
+                pos: self.tok_pos(),
+            })
+        }
     }
-    
+
+    fn eat_id(&mut self) -> ParseResult<'a, (&'a str, TokenPos)> {
+        match self.peek() {
+            TokenKind::Id(name) => {
+                let pos = self.tok_pos();
+                self.advance();
+                Ok((name, pos))
+            }
+            _ => self.err("an identifier"),
+        }
+    }
+
     pub fn parse(&mut self) -> ParseResult<'a, Expr> {
-	self.t()
-    }
-
-    fn t(&mut self) -> ParseResult<'a, Expr<'a>> {
-	let kind = self.peek();
-	match kind {
-	    TokenKind::Num(_) => {
-		let left = self.f()?;
-		self.t_rest(left)
-	    },
-	    TokenKind::LeftParen => {
-		let left = self.f()?;
-		self.t_rest(left)
-	    },
-	    _ => unexpected_token("an arithmetic expression", kind),
-	}
-    }
-
-    fn t_rest(&mut self, left: Expr<'a>) -> ParseResult<'a, Expr<'a>> {
-	if self.is_eof() {
-	    return Ok(left)
-	};
-	
-	let kind = self.peek();
-	match kind {
-	    TokenKind::Times => {
-		let pos = self.tok_pos();
-		self.advance();
-		let right = self.f()?;
-		let expr = Expr::BinOp {
-		    left: Box::new(left),
-		    oper: Op::Times,
-		    right: Box::new(right),
-		    pos,
-		};
-		self.t_rest(expr)
-	    },
-	    _ => Ok(left),
-	}
-    }
-
-    fn f(&mut self) -> ParseResult<'a, Expr<'a>> {
-	let kind = self.peek();
-	let expr = match kind {
-	    TokenKind::Num(x) => Ok(Expr::Int(x)),
-	    _ => unexpected_token("a number", kind),
-	}?;
-	self.advance();
-	Ok(expr)
+        self.parse_expr(0)
+    }
+
+    /// Precedence-climbing core: parse a prefix/primary, then fold trailing
+    /// `op rhs` pairs whose binding power is at least `min_bp`.
+    fn parse_expr(&mut self, min_bp: u8) -> ParseResult<'a, Expr> {
+        let mut left = self.parse_prefix()?;
+
+        while let Some(op) = infix_op(self.peek()) {
+            let bp = left_bp(&op);
+            if bp < min_bp {
+                break;
+            }
+            let comparison = is_comparison(&op);
+            let pos = self.tok_pos();
+            self.advance();
+            let right = self.parse_expr(bp + 1)?;
+            left = match op {
+                InfixOp::Bin(oper) => Expr::BinOp {
+                    left: Box::new(left),
+                    oper,
+                    right: Box::new(right),
+                    pos,
+                },
+                InfixOp::And => Expr::If {
+                    test: Box::new(left),
+                    then_branch: Box::new(right),
+                    else_branch: Some(Box::new(Expr::Int(0))),
+                    pos,
+                },
+                InfixOp::Or => Expr::If {
+                    test: Box::new(left),
+                    then_branch: Box::new(Expr::Int(1)),
+                    else_branch: Some(Box::new(right)),
+                    pos,
+                },
+            };
+            // Comparisons do not chain: reject `a < b < c`.
+            if comparison {
+                if let Some(next) = infix_op(self.peek()) {
+                    if is_comparison(&next) {
+                        return self.err("a non-associative comparison");
+                    }
+                }
+            }
+        }
+
+        Ok(left)
+    }
+
+    fn parse_prefix(&mut self) -> ParseResult<'a, Expr> {
+        if self.is_match(TokenKind::Minus) {
+            let pos = self.tok_pos();
+            self.advance();
+            let operand = self.parse_expr(PREFIX_BP)?;
+            return Ok(Expr::BinOp {
+                left: Box::new(Expr::Int(0)),
+                oper: Op::Minus,
+                right: Box::new(operand),
+                pos,
+            });
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> ParseResult<'a, Expr> {
+        let kind = self.peek();
+        match kind {
+            TokenKind::Num(n) => {
+                self.advance();
+                Ok(Expr::Int(n))
+            }
+            TokenKind::String(s) => {
+                let sym = self.interner.symbol(&s);
+                self.advance();
+                Ok(Expr::String(sym))
+            }
+            TokenKind::Nil => {
+                self.advance();
+                Ok(Expr::Nil)
+            }
+            TokenKind::Break => {
+                let pos = self.tok_pos();
+                self.advance();
+                Ok(Expr::Break(pos))
+            }
+            TokenKind::If => self.parse_if(),
+            TokenKind::While => self.parse_while(),
+            TokenKind::For => self.parse_for(),
+            TokenKind::Let => self.parse_let(),
+            TokenKind::LeftParen => self.parse_seq(),
+            TokenKind::Id(name) => self.parse_id_expr(name),
+            _ => self.err("an expression"),
+        }
+    }
+
+    /// An identifier at expression position is ambiguous until the following
+    /// token is seen: a call, a record literal, an array literal, or an lvalue
+    /// (which may in turn be assigned to).
+    fn parse_id_expr(&mut self, name: &'a str) -> ParseResult<'a, Expr> {
+        let pos = self.tok_pos();
+        let sym = self.interner.symbol(name);
+        self.advance();
+
+        match self.peek() {
+            TokenKind::LeftParen => {
+                self.advance();
+                let args = self.parse_args()?;
+                Ok(Expr::Call {
+                    func: sym,
+                    args,
+                    pos,
+                })
+            }
+            TokenKind::LeftCurly => {
+                self.advance();
+                let fields = self.parse_record_fields()?;
+                Ok(Expr::Record {
+                    fields,
+                    rtype: sym,
+                    pos,
+                })
+            }
+            TokenKind::LeftSquare => {
+                let open = self.tok_pos();
+                self.advance();
+                let index = self.parse_expr(0)?;
+                self.eat_close(TokenKind::RightSquare, "]")?;
+                if self.is_match(TokenKind::Of) {
+                    // `id [size] of init` is an array literal.
+                    self.advance();
+                    let init = self.parse_expr(0)?;
+                    Ok(Expr::Array {
+                        etype: sym,
+                        size: Box::new(index),
+                        init: Box::new(init),
+                        pos,
+                    })
+                } else {
+                    // Otherwise it is a subscripted lvalue.
+                    let var = Var::Subscript(Box::new(Var::Simple(sym, pos)), Box::new(index), open);
+                    self.parse_lvalue_tail(var)
+                }
+            }
+            _ => self.parse_lvalue_tail(Var::Simple(sym, pos)),
+        }
+    }
+
+    /// Extend a partially parsed lvalue with `.field` and `[index]` suffixes,
+    /// then turn it into either an assignment or a plain reference.
+    fn parse_lvalue_tail(&mut self, mut var: Var) -> ParseResult<'a, Expr> {
+        loop {
+            match self.peek() {
+                TokenKind::Period => {
+                    self.advance();
+                    let (field, fpos) = self.eat_id()?;
+                    let fsym = self.interner.symbol(field);
+                    var = Var::Field(Box::new(var), fsym, fpos);
+                }
+                TokenKind::LeftSquare => {
+                    let open = self.tok_pos();
+                    self.advance();
+                    let index = self.parse_expr(0)?;
+                    self.eat_close(TokenKind::RightSquare, "]")?;
+                    var = Var::Subscript(Box::new(var), Box::new(index), open);
+                }
+                _ => break,
+            }
+        }
+
+        if self.is_match(TokenKind::ColonEquals) {
+            let pos = self.tok_pos();
+            self.advance();
+            let expr = self.parse_expr(0)?;
+            Ok(Expr::Assign {
+                var,
+                expr: Box::new(expr),
+                pos,
+            })
+        } else {
+            Ok(Expr::VarRef(Box::new(var)))
+        }
+    }
+
+    fn parse_args(&mut self) -> ParseResult<'a, Vec<Expr>> {
+        let mut args = vec![];
+        if !self.is_match(TokenKind::RightParen) {
+            loop {
+                args.push(self.parse_expr(0)?);
+                if self.is_match(TokenKind::Comma) {
+                    self.advance();
+                } else {
+                    break;
+                }
+            }
+        }
+        self.eat_close(TokenKind::RightParen, ")")?;
+        Ok(args)
+    }
+
+    fn parse_record_fields(&mut self) -> ParseResult<'a, Vec<(Symbol, Box<Expr>, TokenPos)>> {
+        let mut fields = vec![];
+        if !self.is_match(TokenKind::RightCurly) {
+            loop {
+                let (name, fpos) = self.eat_id()?;
+                let sym = self.interner.symbol(name);
+                self.eat(TokenKind::Equals, "=")?;
+                let value = self.parse_expr(0)?;
+                fields.push((sym, Box::new(value), fpos));
+                if self.is_match(TokenKind::Comma) {
+                    self.advance();
+                } else {
+                    break;
+                }
+            }
+        }
+        self.eat_close(TokenKind::RightCurly, "}")?;
+        Ok(fields)
+    }
+
+    /// A parenthesised expression sequence: `()` is unit, a single expression
+    /// is itself, and several semicolon-separated ones form a `Seq`.
+    fn parse_seq(&mut self) -> ParseResult<'a, Expr> {
+        self.advance(); // (
+        if self.is_match(TokenKind::RightParen) {
+            self.advance();
+            return Ok(Expr::Seq(vec![]));
+        }
+        let items = self.parse_expr_seq()?;
+        self.eat_close(TokenKind::RightParen, ")")?;
+        Ok(Self::fold_seq(items))
+    }
+
+    fn parse_expr_seq(&mut self) -> ParseResult<'a, Vec<(Box<Expr>, TokenPos)>> {
+        let mut items = vec![];
+        loop {
+            let pos = self.tok_pos();
+            let expr = self.parse_expr(0)?;
+            items.push((Box::new(expr), pos));
+            if self.is_match(TokenKind::Semicolon) {
+                self.advance();
+            } else {
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    fn fold_seq(mut items: Vec<(Box<Expr>, TokenPos)>) -> Expr {
+        if items.len() == 1 {
+            *items.pop().unwrap().0
+        } else {
+            Expr::Seq(items)
+        }
+    }
+
+    fn parse_if(&mut self) -> ParseResult<'a, Expr> {
+        let pos = self.tok_pos();
+        self.advance(); // if
+        let test = self.parse_expr(0)?;
+        self.eat(TokenKind::Then, "then")?;
+        let then_branch = self.parse_expr(0)?;
+        let else_branch = if self.is_match(TokenKind::Else) {
+            self.advance();
+            Some(Box::new(self.parse_expr(0)?))
+        } else {
+            None
+        };
+        Ok(Expr::If {
+            test: Box::new(test),
+            then_branch: Box::new(then_branch),
+            else_branch,
+            pos,
+        })
+    }
+
+    fn parse_while(&mut self) -> ParseResult<'a, Expr> {
+        let pos = self.tok_pos();
+        self.advance(); // while
+        let test = self.parse_expr(0)?;
+        self.eat(TokenKind::Do, "do")?;
+        let body = self.parse_expr(0)?;
+        Ok(Expr::While {
+            test: Box::new(test),
+            body: Box::new(body),
+            pos,
+        })
     }
-}
 
+    fn parse_for(&mut self) -> ParseResult<'a, Expr> {
+        let pos = self.tok_pos();
+        self.advance(); // for
+        let (name, _) = self.eat_id()?;
+        let var = self.interner.symbol(name);
+        self.eat(TokenKind::ColonEquals, ":=")?;
+        let lo = self.parse_expr(0)?;
+        self.eat(TokenKind::To, "to")?;
+        let hi = self.parse_expr(0)?;
+        self.eat(TokenKind::Do, "do")?;
+        let body = self.parse_expr(0)?;
+        Ok(Expr::For {
+            var,
+            // Filled in by the `escape` pass, once the whole tree exists.
+            escape: Cell::new(false),
+            lo: Box::new(lo),
+            hi: Box::new(hi),
+            body: Box::new(body),
+            pos,
+        })
+    }
+
+    fn parse_let(&mut self) -> ParseResult<'a, Expr> {
+        let pos = self.tok_pos();
+        self.advance(); // let
+        let decls = self.parse_decls()?;
+        self.eat(TokenKind::In, "in")?;
+        let body = if self.is_match(TokenKind::End) {
+            Expr::Seq(vec![])
+        } else {
+            Self::fold_seq(self.parse_expr_seq()?)
+        };
+        self.eat(TokenKind::End, "end")?;
+        Ok(Expr::Let {
+            decls,
+            body: Box::new(body),
+            pos,
+        })
+    }
+
+    /// Consecutive `type` and `function` declarations group into a single
+    /// mutually-recursive batch, matching the `Vec` shape of the AST.
+    fn parse_decls(&mut self) -> ParseResult<'a, Vec<Box<Decl>>> {
+        let mut decls = vec![];
+        loop {
+            match self.peek() {
+                TokenKind::Type => {
+                    let mut group = vec![];
+                    while self.is_match(TokenKind::Type) {
+                        group.push(Box::new(self.parse_typedecl()?));
+                    }
+                    decls.push(Box::new(Decl::Type(group)));
+                }
+                TokenKind::Function => {
+                    let mut group = vec![];
+                    while self.is_match(TokenKind::Function) {
+                        group.push(Box::new(self.parse_fundecl()?));
+                    }
+                    decls.push(Box::new(Decl::Function(group)));
+                }
+                TokenKind::Var => decls.push(Box::new(self.parse_vardecl()?)),
+                _ => break,
+            }
+        }
+        Ok(decls)
+    }
+
+    fn parse_vardecl(&mut self) -> ParseResult<'a, Decl> {
+        let pos = self.tok_pos();
+        self.advance(); // var
+        let (name, _) = self.eat_id()?;
+        let name = self.interner.symbol(name);
+        let vtype = self.parse_type_annotation()?;
+        self.eat(TokenKind::ColonEquals, ":=")?;
+        let init = self.parse_expr(0)?;
+        Ok(Decl::Var {
+            name,
+            // Filled in by the `escape` pass, once the whole tree exists.
+            escape: Cell::new(false),
+            vtype,
+            init: Box::new(init),
+            pos,
+        })
+    }
+
+    fn parse_typedecl(&mut self) -> ParseResult<'a, Typedecl> {
+        let pos = self.tok_pos();
+        self.advance(); // type
+        let (name, _) = self.eat_id()?;
+        let name = self.interner.symbol(name);
+        self.eat(TokenKind::Equals, "=")?;
+        let ty = self.parse_ty()?;
+        Ok(Typedecl { name, ty, pos })
+    }
+
+    fn parse_fundecl(&mut self) -> ParseResult<'a, Fundecl> {
+        let pos = self.tok_pos();
+        self.advance(); // function
+        let (name, _) = self.eat_id()?;
+        let name = self.interner.symbol(name);
+        self.eat(TokenKind::LeftParen, "(")?;
+        let params = self.parse_fields()?;
+        self.eat_close(TokenKind::RightParen, ")")?;
+        let result = self.parse_type_annotation()?;
+        self.eat(TokenKind::Equals, "=")?;
+        let body = self.parse_expr(0)?;
+        Ok(Fundecl {
+            name,
+            params,
+            result,
+            body: Box::new(body),
+            pos,
+        })
+    }
+
+    /// Optional `: type-id` suffix shared by var and function declarations.
+    fn parse_type_annotation(&mut self) -> ParseResult<'a, Option<(Symbol, TokenPos)>> {
+        if self.is_match(TokenKind::Colon) {
+            self.advance();
+            let (name, tpos) = self.eat_id()?;
+            Ok(Some((self.interner.symbol(name), tpos)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn parse_ty(&mut self) -> ParseResult<'a, Ty> {
+        match self.peek() {
+            TokenKind::Id(name) => {
+                let pos = self.tok_pos();
+                self.advance();
+                Ok(Ty::Name(self.interner.symbol(name), pos))
+            }
+            TokenKind::LeftCurly => {
+                self.advance();
+                let fields = self.parse_fields()?;
+                self.eat_close(TokenKind::RightCurly, "}")?;
+                Ok(Ty::Record(fields))
+            }
+            TokenKind::Array => {
+                self.advance();
+                self.eat(TokenKind::Of, "of")?;
+                let (name, tpos) = self.eat_id()?;
+                Ok(Ty::Array(self.interner.symbol(name), tpos))
+            }
+            _ => self.err("a type"),
+        }
+    }
+
+    /// Comma-separated `id : type-id` fields for record types and function
+    /// parameter lists.
+    fn parse_fields(&mut self) -> ParseResult<'a, Vec<Box<Field>>> {
+        let mut fields = vec![];
+        if matches!(self.peek(), TokenKind::Id(_)) {
+            loop {
+                let (name, fpos) = self.eat_id()?;
+                let name = self.interner.symbol(name);
+                self.eat(TokenKind::Colon, ":")?;
+                let (ty, _) = self.eat_id()?;
+                let ftype = self.interner.symbol(ty);
+                fields.push(Box::new(Field {
+                    name,
+                    // Filled in by the `escape` pass, once the whole tree exists.
+                    escape: Cell::new(false),
+                    ftype,
+                    pos: fpos,
+                }));
+                if self.is_match(TokenKind::Comma) {
+                    self.advance();
+                } else {
+                    break;
+                }
+            }
+        }
+        Ok(fields)
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::lexer::Lexer;
+
+    fn toks(src: &str) -> Vec<Token> {
+        Lexer::new(src).collect()
+    }
 
     #[test]
     fn products() {
-	let toks = Lexer::new("3*4").collect();
-	let mut parser = Parser::new(toks);
-	let result = parser.parse();
-	println!("{:?}", result);
-	assert!(match result {
-	    Ok(Expr::BinOp { left, oper, right, pos }) => true,
-	    _ => false,
-	});
+        let mut parser = Parser::new(toks("3*4"));
+        let result = parser.parse();
+        assert!(matches!(
+            result,
+            Ok(Expr::BinOp {
+                oper: Op::Times,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn precedence_times_over_plus() {
+        // 1 + 2 * 3  parses as  1 + (2 * 3)
+        let mut parser = Parser::new(toks("1+2*3"));
+        match parser.parse().unwrap() {
+            Expr::BinOp {
+                oper: Op::Plus,
+                right,
+                ..
+            } => assert!(matches!(*right, Expr::BinOp { oper: Op::Times, .. })),
+            other => panic!("unexpected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn left_associative_minus() {
+        // 1 - 2 - 3  parses as  (1 - 2) - 3
+        let mut parser = Parser::new(toks("1-2-3"));
+        match parser.parse().unwrap() {
+            Expr::BinOp {
+                oper: Op::Minus,
+                left,
+                ..
+            } => assert!(matches!(*left, Expr::BinOp { oper: Op::Minus, .. })),
+            other => panic!("unexpected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn comparisons_do_not_chain() {
+        let mut parser = Parser::new(toks("1<2<3"));
+        assert!(parser.parse().is_err());
+    }
+
+    #[test]
+    fn and_desugars_to_if() {
+        let mut parser = Parser::new(toks("1&0"));
+        assert!(matches!(
+            parser.parse().unwrap(),
+            Expr::If {
+                else_branch: Some(_),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn unary_minus() {
+        // -3 * 4  parses as  (0 - 3) * 4
+        let mut parser = Parser::new(toks("-3*4"));
+        assert!(matches!(
+            parser.parse().unwrap(),
+            Expr::BinOp { oper: Op::Times, .. }
+        ));
+    }
+
+    #[test]
+    fn assignment() {
+        let mut parser = Parser::new(toks("x := 1 + 2"));
+        assert!(matches!(parser.parse().unwrap(), Expr::Assign { .. }));
+    }
+
+    #[test]
+    fn let_in_end() {
+        let mut parser = Parser::new(toks("let var x : int := 3 in x + 1 end"));
+        assert!(matches!(parser.parse().unwrap(), Expr::Let { .. }));
+    }
+
+    #[test]
+    fn call_and_subscript() {
+        let mut parser = Parser::new(toks("f(a, b[1])"));
+        assert!(matches!(parser.parse().unwrap(), Expr::Call { .. }));
+    }
+
+    #[test]
+    fn if_then_else() {
+        let mut parser = Parser::new(toks("if 1 then 2 else 3"));
+        assert!(matches!(
+            parser.parse().unwrap(),
+            Expr::If {
+                else_branch: Some(_),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn missing_delimiter_reports_span() {
+        let mut parser = Parser::new(toks("f(1 2)"));
+        let err = parser.parse().unwrap_err();
+        assert!(matches!(err, ParseError::MissingDelimiter { delimiter: ")", .. }));
+    }
+
+    #[test]
+    fn unexpected_eof() {
+        let mut parser = Parser::new(toks("1 +"));
+        assert!(matches!(
+            parser.parse().unwrap_err(),
+            ParseError::UnexpectedEof { .. }
+        ));
+    }
+
+    #[test]
+    fn error_renders_a_caret() {
+        let source = "1 +";
+        let mut parser = Parser::new(toks(source));
+        let diag = parser.parse().unwrap_err().to_diagnostic(source);
+        assert!(diag.render(source).contains('^'));
     }
 }
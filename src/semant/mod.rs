@@ -0,0 +1,632 @@
+//! Semantic analysis: resolves every `Symbol` in the tree against scoped
+//! value/type environments and statically type-checks each node.
+//!
+//! Mutually recursive `Decl::Type` and `Decl::Function` groups (already
+//! grouped by the parser) are each resolved in two passes -- headers first,
+//! then bodies -- so forward and self references within a group work.
+
+mod types;
+
+pub use types::{EnvEntry, Ty, TypeEnv, TypeId, TypeTable, ValueEnv};
+
+use std::collections::HashMap;
+
+use crate::ast::{Decl, Expr, Fundecl, Op, Ty as AstTy, Typedecl, Var};
+use crate::diag::{Diagnostic, Diagnostics};
+use crate::intern::{Interner, Symbol};
+use crate::lexer::TokenPos as Pos;
+
+/// Type-check `expr` (a whole program) and return its type along with every
+/// diagnostic collected along the way. An empty `Diagnostics` means the
+/// program is well-typed.
+pub fn check(interner: &mut Interner, expr: &Expr) -> (Ty, Diagnostics) {
+    let mut checker = Checker::new(interner);
+    let ty = checker.check_expr(interner, expr);
+    (ty, checker.diags)
+}
+
+fn add_fun(interner: &mut Interner, values: &mut ValueEnv, name: &str, params: Vec<Ty>, result: Ty) {
+    let sym = interner.symbol(name);
+    values.insert(sym, EnvEntry::Fun { params, result });
+}
+
+struct Checker {
+    values: ValueEnv,
+    types: TypeEnv,
+    table: TypeTable,
+    diags: Diagnostics,
+    loop_depth: u32,
+}
+
+impl Checker {
+    fn new(interner: &mut Interner) -> Self {
+        let mut types = TypeEnv::new();
+        types.insert(interner.symbol("int"), Ty::Int);
+        types.insert(interner.symbol("string"), Ty::String);
+
+        let mut values = ValueEnv::new();
+        add_fun(interner, &mut values, "print", vec![Ty::String], Ty::Unit);
+        add_fun(interner, &mut values, "flush", vec![], Ty::Unit);
+        add_fun(interner, &mut values, "getchar", vec![], Ty::String);
+        add_fun(interner, &mut values, "ord", vec![Ty::String], Ty::Int);
+        add_fun(interner, &mut values, "chr", vec![Ty::Int], Ty::String);
+        add_fun(interner, &mut values, "size", vec![Ty::String], Ty::Int);
+        add_fun(
+            interner,
+            &mut values,
+            "substring",
+            vec![Ty::String, Ty::Int, Ty::Int],
+            Ty::String,
+        );
+        add_fun(
+            interner,
+            &mut values,
+            "concat",
+            vec![Ty::String, Ty::String],
+            Ty::String,
+        );
+        add_fun(interner, &mut values, "not", vec![Ty::Int], Ty::Int);
+        add_fun(interner, &mut values, "exit", vec![Ty::Int], Ty::Unit);
+
+        Checker {
+            values,
+            types,
+            table: TypeTable::new(),
+            diags: Diagnostics::new(),
+            loop_depth: 0,
+        }
+    }
+
+    fn error(&mut self, pos: Pos, message: impl Into<String>) -> Ty {
+        self.diags.push(Diagnostic::new(pos, message));
+        Ty::Error
+    }
+
+    fn resolve_type(&mut self, sym: Symbol, pos: Pos) -> Option<Ty> {
+        match self.types.get(sym) {
+            Some(ty) => Some(*ty),
+            None => {
+                self.error(pos, "reference to an undeclared type");
+                None
+            }
+        }
+    }
+
+    fn check_expr(&mut self, interner: &mut Interner, expr: &Expr) -> Ty {
+        match expr {
+            Expr::Nil => Ty::Nil,
+            Expr::Int(_) => Ty::Int,
+            Expr::String(_) => Ty::String,
+            Expr::VarRef(var) => self.check_var(interner, var),
+            Expr::Call { func, args, pos } => {
+                let entry = self.values.get(*func).cloned();
+                let (params, result) = match entry {
+                    Some(EnvEntry::Fun { params, result }) => (params, result),
+                    Some(EnvEntry::Var(_)) => {
+                        return self.error(*pos, "called a variable as if it were a function")
+                    }
+                    None => return self.error(*pos, "call to an undeclared function"),
+                };
+                if params.len() != args.len() {
+                    self.error(
+                        *pos,
+                        format!("expected {} argument(s), found {}", params.len(), args.len()),
+                    );
+                }
+                for (i, arg) in args.iter().enumerate() {
+                    let arg_ty = self.check_expr(interner, arg);
+                    if let Some(expected) = params.get(i) {
+                        if !arg_ty.compatible(expected) {
+                            self.error(*pos, format!("argument {} has the wrong type", i + 1));
+                        }
+                    }
+                }
+                result
+            }
+            Expr::BinOp {
+                left, oper, right, pos,
+            } => {
+                let l = self.check_expr(interner, left);
+                let r = self.check_expr(interner, right);
+                self.check_binop(oper, l, r, *pos)
+            }
+            Expr::Record { fields, rtype, pos } => self.check_record(interner, fields, *rtype, *pos),
+            Expr::Seq(items) => {
+                let mut last = Ty::Unit;
+                for (e, _) in items {
+                    last = self.check_expr(interner, e);
+                }
+                last
+            }
+            Expr::Assign { var, expr, pos } => {
+                let var_ty = self.check_var(interner, var);
+                let val_ty = self.check_expr(interner, expr);
+                if !val_ty.compatible(&var_ty) {
+                    self.error(*pos, "assigned value does not match the variable's type");
+                }
+                Ty::Unit
+            }
+            Expr::If {
+                test,
+                then_branch,
+                else_branch,
+                pos,
+            } => {
+                let test_ty = self.check_expr(interner, test);
+                if !test_ty.compatible(&Ty::Int) {
+                    self.error(*pos, "`if` condition must be an int");
+                }
+                let then_ty = self.check_expr(interner, then_branch);
+                match else_branch {
+                    Some(e) => {
+                        let else_ty = self.check_expr(interner, e);
+                        if !then_ty.compatible(&else_ty) {
+                            self.error(*pos, "`if` branches have different types");
+                            Ty::Error
+                        } else if then_ty == Ty::Error {
+                            else_ty
+                        } else {
+                            then_ty
+                        }
+                    }
+                    None => {
+                        if !then_ty.compatible(&Ty::Unit) {
+                            self.error(*pos, "`if` with no `else` must produce unit");
+                        }
+                        Ty::Unit
+                    }
+                }
+            }
+            Expr::While { test, body, pos } => {
+                let test_ty = self.check_expr(interner, test);
+                if !test_ty.compatible(&Ty::Int) {
+                    self.error(*pos, "`while` condition must be an int");
+                }
+                self.loop_depth += 1;
+                let body_ty = self.check_expr(interner, body);
+                self.loop_depth -= 1;
+                if !body_ty.compatible(&Ty::Unit) {
+                    self.error(*pos, "`while` body must produce unit");
+                }
+                Ty::Unit
+            }
+            Expr::For {
+                var, lo, hi, body, pos, ..
+            } => {
+                let lo_ty = self.check_expr(interner, lo);
+                let hi_ty = self.check_expr(interner, hi);
+                if !lo_ty.compatible(&Ty::Int) || !hi_ty.compatible(&Ty::Int) {
+                    self.error(*pos, "`for` bounds must be ints");
+                }
+                self.values.push();
+                self.values.insert(*var, EnvEntry::Var(Ty::Int));
+                self.loop_depth += 1;
+                let body_ty = self.check_expr(interner, body);
+                self.loop_depth -= 1;
+                self.values.pop();
+                if !body_ty.compatible(&Ty::Unit) {
+                    self.error(*pos, "`for` body must produce unit");
+                }
+                Ty::Unit
+            }
+            Expr::Break(pos) => {
+                if self.loop_depth == 0 {
+                    self.error(*pos, "`break` outside of a loop");
+                }
+                Ty::Unit
+            }
+            Expr::Let { decls, body, .. } => {
+                self.values.push();
+                self.types.push();
+                for d in decls {
+                    self.check_decl(interner, d);
+                }
+                let ty = self.check_expr(interner, body);
+                self.types.pop();
+                self.values.pop();
+                ty
+            }
+            Expr::Array {
+                etype, size, init, pos,
+            } => self.check_array(interner, *etype, size, init, *pos),
+        }
+    }
+
+    fn check_var(&mut self, interner: &mut Interner, var: &Var) -> Ty {
+        match var {
+            Var::Simple(sym, pos) => match self.values.get(*sym).cloned() {
+                Some(EnvEntry::Var(ty)) => ty,
+                Some(EnvEntry::Fun { .. }) => {
+                    self.error(*pos, "expected a variable, found a function")
+                }
+                None => self.error(*pos, "reference to an undeclared variable"),
+            },
+            Var::Field(base, field, pos) => {
+                let base_ty = self.check_var(interner, base);
+                match base_ty {
+                    Ty::Record(id) => match self.table.field_type(id, *field) {
+                        Some(ty) => ty,
+                        None => self.error(*pos, "no such field on this record"),
+                    },
+                    Ty::Error => Ty::Error,
+                    _ => self.error(*pos, "field access on a non-record type"),
+                }
+            }
+            Var::Subscript(base, index, pos) => {
+                let base_ty = self.check_var(interner, base);
+                let index_ty = self.check_expr(interner, index);
+                if !index_ty.compatible(&Ty::Int) {
+                    self.error(*pos, "array index must be an int");
+                }
+                match base_ty {
+                    Ty::Array(id) => self.table.array_elem(id),
+                    Ty::Error => Ty::Error,
+                    _ => self.error(*pos, "subscript on a non-array type"),
+                }
+            }
+        }
+    }
+
+    fn check_binop(&mut self, op: &Op, l: Ty, r: Ty, pos: Pos) -> Ty {
+        match op {
+            Op::Plus | Op::Minus | Op::Times | Op::Divide => {
+                if l.compatible(&Ty::Int) && r.compatible(&Ty::Int) {
+                    Ty::Int
+                } else {
+                    self.error(pos, "arithmetic requires int operands")
+                }
+            }
+            Op::Eq | Op::Neq => {
+                if l.compatible(&r) {
+                    Ty::Int
+                } else {
+                    self.error(pos, "`=`/`<>` requires operands of the same type")
+                }
+            }
+            Op::Lt | Op::Le | Op::Gt | Op::Ge => {
+                let ok = matches!((l, r), (Ty::Int, Ty::Int) | (Ty::String, Ty::String))
+                    || l == Ty::Error
+                    || r == Ty::Error;
+                if ok {
+                    Ty::Int
+                } else {
+                    self.error(pos, "ordering comparisons require two ints or two strings")
+                }
+            }
+        }
+    }
+
+    fn check_record(
+        &mut self,
+        interner: &mut Interner,
+        fields: &[(Symbol, Box<Expr>, Pos)],
+        rtype: Symbol,
+        pos: Pos,
+    ) -> Ty {
+        let declared = match self.types.get(rtype).copied() {
+            Some(Ty::Record(id)) => id,
+            Some(Ty::Error) => return Ty::Error,
+            Some(_) => return self.error(pos, "not a record type"),
+            None => return self.error(pos, "reference to an undeclared type"),
+        };
+        let expected = self.table.record_fields(declared).to_vec();
+        if expected.len() != fields.len() {
+            self.error(
+                pos,
+                format!("expected {} field(s), found {}", expected.len(), fields.len()),
+            );
+        }
+        for (name, e, fpos) in fields {
+            let val_ty = self.check_expr(interner, e);
+            match expected.iter().find(|(n, _)| n == name) {
+                Some((_, expected_ty)) => {
+                    if !val_ty.compatible(expected_ty) {
+                        self.error(*fpos, "record field has the wrong type");
+                    }
+                }
+                None => {
+                    self.error(*fpos, "no such field on this record type");
+                }
+            }
+        }
+        Ty::Record(declared)
+    }
+
+    fn check_array(
+        &mut self,
+        interner: &mut Interner,
+        etype: Symbol,
+        size: &Expr,
+        init: &Expr,
+        pos: Pos,
+    ) -> Ty {
+        let declared = match self.types.get(etype).copied() {
+            Some(Ty::Array(id)) => id,
+            Some(Ty::Error) => return Ty::Error,
+            Some(_) => return self.error(pos, "not an array type"),
+            None => return self.error(pos, "reference to an undeclared type"),
+        };
+        let size_ty = self.check_expr(interner, size);
+        if !size_ty.compatible(&Ty::Int) {
+            self.error(pos, "array size must be an int");
+        }
+        let init_ty = self.check_expr(interner, init);
+        let elem_ty = self.table.array_elem(declared);
+        if !init_ty.compatible(&elem_ty) {
+            self.error(pos, "array initial value does not match the element type");
+        }
+        Ty::Array(declared)
+    }
+
+    fn check_decl(&mut self, interner: &mut Interner, decl: &Decl) {
+        match decl {
+            Decl::Var {
+                name, vtype, init, pos, ..
+            } => {
+                let init_ty = self.check_expr(interner, init);
+                match vtype {
+                    Some((tsym, tpos)) => {
+                        let declared = self.resolve_type(*tsym, *tpos).unwrap_or(Ty::Error);
+                        if !init_ty.compatible(&declared) {
+                            self.error(*pos, "variable's initializer does not match its declared type");
+                        }
+                        self.values.insert(*name, EnvEntry::Var(declared));
+                    }
+                    None => {
+                        if init_ty == Ty::Nil {
+                            self.error(*pos, "`nil` requires an explicit record type annotation");
+                        }
+                        self.values.insert(*name, EnvEntry::Var(init_ty));
+                    }
+                }
+            }
+            Decl::Type(group) => self.check_type_group(group),
+            Decl::Function(group) => self.check_function_group(interner, group),
+        }
+    }
+
+    /// Resolve a mutually recursive batch of `type` declarations in two
+    /// passes: first hand out a `TypeId` for every record/array so sibling
+    /// declarations (including the declaration itself) can refer to it,
+    /// then fill in field lists, element types, and plain-name aliases.
+    fn check_type_group(&mut self, group: &[Box<Typedecl>]) {
+        let group_map: HashMap<Symbol, &Typedecl> =
+            group.iter().map(|td| (td.name, td.as_ref())).collect();
+
+        for td in group {
+            match &td.ty {
+                AstTy::Record(_) => {
+                    let id = self.table.fresh_record();
+                    self.types.insert(td.name, Ty::Record(id));
+                }
+                AstTy::Array(_, _) => {
+                    let id = self.table.fresh_array();
+                    self.types.insert(td.name, Ty::Array(id));
+                }
+                AstTy::Name(_, _) => {}
+            }
+        }
+
+        for td in group {
+            match &td.ty {
+                AstTy::Record(fields) => {
+                    let id = match self.types.get(td.name) {
+                        Some(Ty::Record(id)) => *id,
+                        _ => unreachable!("pass 1 always installs a Record placeholder"),
+                    };
+                    let mut resolved = Vec::with_capacity(fields.len());
+                    for f in fields {
+                        let fty = self.resolve_type(f.ftype, f.pos).unwrap_or(Ty::Error);
+                        resolved.push((f.name, fty));
+                    }
+                    self.table.set_record_fields(id, resolved);
+                }
+                AstTy::Array(elem, epos) => {
+                    let id = match self.types.get(td.name) {
+                        Some(Ty::Array(id)) => *id,
+                        _ => unreachable!("pass 1 always installs an Array placeholder"),
+                    };
+                    let ety = self.resolve_type(*elem, *epos).unwrap_or(Ty::Error);
+                    self.table.set_array_elem(id, ety);
+                }
+                AstTy::Name(target, tpos) => {
+                    if self.types.get(td.name).is_none() {
+                        let mut seen = vec![td.name];
+                        let resolved = self.resolve_name_alias(*target, *tpos, &group_map, &mut seen);
+                        self.types.insert(td.name, resolved);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Chase a chain of plain `type a = b` aliases within the current
+    /// group, reporting `illegal cycle` rather than overflowing the stack
+    /// if it never reaches a `Record`/`Array`/builtin.
+    fn resolve_name_alias(
+        &mut self,
+        sym: Symbol,
+        pos: Pos,
+        group: &HashMap<Symbol, &Typedecl>,
+        seen: &mut Vec<Symbol>,
+    ) -> Ty {
+        if let Some(ty) = self.types.get(sym) {
+            return *ty;
+        }
+        if seen.contains(&sym) {
+            return self.error(pos, "illegal cycle in type declaration");
+        }
+        seen.push(sym);
+        match group.get(&sym) {
+            Some(td) => match &td.ty {
+                AstTy::Name(target, tpos) => {
+                    let resolved = self.resolve_name_alias(*target, *tpos, group, seen);
+                    self.types.insert(sym, resolved);
+                    resolved
+                }
+                _ => unreachable!("record/array members were resolved in pass 1"),
+            },
+            None => self.error(pos, "reference to an undeclared type"),
+        }
+    }
+
+    /// Resolve a mutually recursive batch of `function` declarations in two
+    /// passes: first register every signature so the group can call each
+    /// other (and recurse) regardless of order, then check each body.
+    fn check_function_group(&mut self, interner: &mut Interner, group: &[Box<Fundecl>]) {
+        let mut signatures = Vec::with_capacity(group.len());
+        for fd in group {
+            let mut params = Vec::with_capacity(fd.params.len());
+            for p in &fd.params {
+                params.push(self.resolve_type(p.ftype, p.pos).unwrap_or(Ty::Error));
+            }
+            let result = match &fd.result {
+                Some((sym, pos)) => self.resolve_type(*sym, *pos).unwrap_or(Ty::Error),
+                None => Ty::Unit,
+            };
+            self.values.insert(
+                fd.name,
+                EnvEntry::Fun {
+                    params: params.clone(),
+                    result,
+                },
+            );
+            signatures.push((params, result));
+        }
+
+        for (fd, (params, result)) in group.iter().zip(signatures) {
+            self.values.push();
+            for (p, ty) in fd.params.iter().zip(&params) {
+                self.values.insert(p.name, EnvEntry::Var(*ty));
+            }
+            let body_ty = self.check_expr(interner, &fd.body);
+            self.values.pop();
+            if !body_ty.compatible(&result) {
+                self.error(fd.pos, "function body does not match its declared result type");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn check_source(source: &str) -> (Ty, Diagnostics) {
+        let mut parser = Parser::new(Lexer::new(source).collect());
+        let expr = parser.parse().expect("source should parse");
+        check(parser.interner_mut(), &expr)
+    }
+
+    #[test]
+    fn arithmetic_is_int() {
+        let (ty, diags) = check_source("1 + 2 * 3");
+        assert_eq!(ty, Ty::Int);
+        assert!(diags.is_empty());
+    }
+
+    #[test]
+    fn arithmetic_on_strings_is_an_error() {
+        let (_, diags) = check_source("\"a\" + 1");
+        assert_eq!(diags.len(), 1);
+    }
+
+    #[test]
+    fn if_without_else_must_be_unit() {
+        let (_, diags) = check_source("if 1 then 2");
+        assert_eq!(diags.len(), 1);
+    }
+
+    #[test]
+    fn if_branches_must_agree() {
+        let (_, diags) = check_source("if 1 then 2 else \"a\"");
+        assert_eq!(diags.len(), 1);
+    }
+
+    #[test]
+    fn break_outside_a_loop_is_an_error() {
+        let (_, diags) = check_source("break");
+        assert_eq!(diags.len(), 1);
+    }
+
+    #[test]
+    fn break_inside_a_while_loop_is_fine() {
+        let (_, diags) = check_source("while 1 do break");
+        assert!(diags.is_empty());
+    }
+
+    #[test]
+    fn for_loop_variable_is_bound_as_int() {
+        // `chr` takes an int, so this only checks cleanly if `i` is bound as one.
+        let (_, diags) = check_source("for i := 1 to 10 do print(chr(i))");
+        assert!(diags.is_empty());
+    }
+
+    #[test]
+    fn for_loop_body_must_be_unit() {
+        let (_, diags) = check_source("for i := 1 to 10 do i + 1");
+        assert_eq!(diags.len(), 1);
+    }
+
+    #[test]
+    fn mutually_recursive_record_types_resolve() {
+        let (ty, diags) = check_source(
+            "let
+               type tree = {value: int, kids: treelist}
+               type treelist = {head: tree, tail: treelist}
+               var t: tree := nil
+             in
+               t
+             end",
+        );
+        assert!(diags.is_empty());
+        assert!(matches!(ty, Ty::Record(_)));
+    }
+
+    #[test]
+    fn illegal_type_alias_cycle_is_reported() {
+        let (_, diags) = check_source(
+            "let
+               type a = b
+               type b = a
+             in
+               0
+             end",
+        );
+        assert_eq!(diags.len(), 1);
+    }
+
+    #[test]
+    fn mutually_recursive_functions_typecheck() {
+        let (_, diags) = check_source(
+            "let
+               function is_even(n: int): int = if n = 0 then 1 else is_odd(n - 1)
+               function is_odd(n: int): int = if n = 0 then 0 else is_even(n - 1)
+             in
+               is_even(4)
+             end",
+        );
+        assert!(diags.is_empty());
+    }
+
+    #[test]
+    fn call_argument_count_mismatch_is_reported() {
+        let (_, diags) = check_source("let function f(x: int): int = x in f(1, 2) end");
+        assert_eq!(diags.len(), 1);
+    }
+
+    #[test]
+    fn array_element_type_mismatch_is_reported() {
+        let (_, diags) = check_source(
+            "let
+               type intarray = array of int
+               var a := intarray [10] of \"oops\"
+             in
+               a
+             end",
+        );
+        assert_eq!(diags.len(), 1);
+    }
+}
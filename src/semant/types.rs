@@ -0,0 +1,169 @@
+//! Resolved types used by semantic analysis, plus the scoped environments
+//! `Checker` pushes/pops around `let`, function bodies, and `for` loop
+//! variables.
+
+use std::collections::HashMap;
+
+use crate::intern::Symbol;
+
+/// A record or array type's backing storage is looked up by this id rather
+/// than compared structurally -- two records with identical fields are
+/// still distinct types in Tiger unless they share a declaration.
+pub type TypeId = u32;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ty {
+    Int,
+    String,
+    Nil,
+    Unit,
+    Record(TypeId),
+    Array(TypeId),
+    /// Stands in for a type that already produced a diagnostic, so one
+    /// mistake doesn't cascade into a wall of unrelated errors.
+    Error,
+}
+
+impl Ty {
+    /// Type compatibility for assignment/equality/argument-passing contexts:
+    /// `nil` is compatible with any record type, and `Error` swallows
+    /// mismatches it would otherwise cause.
+    pub fn compatible(&self, other: &Ty) -> bool {
+        match (self, other) {
+            (Ty::Error, _) | (_, Ty::Error) => true,
+            (Ty::Nil, Ty::Record(_)) | (Ty::Record(_), Ty::Nil) => true,
+            (a, b) => a == b,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum EnvEntry {
+    Var(Ty),
+    Fun { params: Vec<Ty>, result: Ty },
+}
+
+/// A stack of scopes mapping names to their resolved meaning. Pushed on
+/// entry to a `let`, function body, or `for` loop, popped on exit.
+pub struct Scoped<V> {
+    scopes: Vec<HashMap<Symbol, V>>,
+}
+
+impl<V: Clone> Scoped<V> {
+    pub fn new() -> Self {
+        Scoped {
+            scopes: vec![HashMap::new()],
+        }
+    }
+
+    pub fn push(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    pub fn pop(&mut self) {
+        self.scopes.pop();
+    }
+
+    pub fn insert(&mut self, name: Symbol, value: V) {
+        self.scopes.last_mut().unwrap().insert(name, value);
+    }
+
+    pub fn get(&self, name: Symbol) -> Option<&V> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(v) = scope.get(&name) {
+                return Some(v);
+            }
+        }
+        None
+    }
+}
+
+pub type ValueEnv = Scoped<EnvEntry>;
+pub type TypeEnv = Scoped<Ty>;
+
+/// Backing storage for record field lists and array element types, keyed
+/// by the `TypeId`s that `Ty::Record`/`Ty::Array` carry.
+#[derive(Default)]
+pub struct TypeTable {
+    records: HashMap<TypeId, Vec<(Symbol, Ty)>>,
+    arrays: HashMap<TypeId, Ty>,
+    next_id: TypeId,
+}
+
+impl TypeTable {
+    pub fn new() -> Self {
+        TypeTable::default()
+    }
+
+    /// Allocate an id for a record type before its field list is known, so
+    /// mutually recursive declarations can refer to it.
+    pub fn fresh_record(&mut self) -> TypeId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.records.insert(id, Vec::new());
+        id
+    }
+
+    /// Allocate an id for an array type before its element type is known.
+    pub fn fresh_array(&mut self) -> TypeId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.arrays.insert(id, Ty::Error);
+        id
+    }
+
+    pub fn set_record_fields(&mut self, id: TypeId, fields: Vec<(Symbol, Ty)>) {
+        self.records.insert(id, fields);
+    }
+
+    pub fn set_array_elem(&mut self, id: TypeId, elem: Ty) {
+        self.arrays.insert(id, elem);
+    }
+
+    pub fn record_fields(&self, id: TypeId) -> &[(Symbol, Ty)] {
+        self.records.get(&id).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn array_elem(&self, id: TypeId) -> Ty {
+        self.arrays.get(&id).copied().unwrap_or(Ty::Error)
+    }
+
+    pub fn field_type(&self, id: TypeId, field: Symbol) -> Option<Ty> {
+        self.records
+            .get(&id)?
+            .iter()
+            .find(|(name, _)| *name == field)
+            .map(|(_, ty)| *ty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intern::Interner;
+
+    #[test]
+    fn scoped_lookup_sees_outer_scopes() {
+        let mut interner = Interner::new();
+        let a = interner.symbol("a");
+        let mut env: Scoped<Ty> = Scoped::new();
+        env.insert(a, Ty::Int);
+        env.push();
+        assert_eq!(env.get(a), Some(&Ty::Int));
+        env.pop();
+        assert_eq!(env.get(a), Some(&Ty::Int));
+    }
+
+    #[test]
+    fn shadowing_is_undone_on_pop() {
+        let mut interner = Interner::new();
+        let a = interner.symbol("a");
+        let mut env: Scoped<Ty> = Scoped::new();
+        env.insert(a, Ty::Int);
+        env.push();
+        env.insert(a, Ty::String);
+        assert_eq!(env.get(a), Some(&Ty::String));
+        env.pop();
+        assert_eq!(env.get(a), Some(&Ty::Int));
+    }
+}